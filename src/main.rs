@@ -1,17 +1,34 @@
-use crate::ReleaseType::{Major, Minor, Patch};
+use crate::DevStyle::{Next, Restore};
+use crate::ReleaseType::{Calendar, Major, Minor, Patch, Prerelease};
 use anyhow::{anyhow, bail, Context as _, Error, Result as ARes};
-use clap::{crate_name, crate_version, App, Arg};
+use clap::{crate_name, crate_version, App, Arg, ArgMatches, Values};
 use fehler::throws;
 use regex::{Captures, Regex};
 use semver::{Identifier, Version, VersionReq};
-use std::env::set_current_dir;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::process::{Command, Output};
+use std::env::{set_current_dir, temp_dir, var, VarError};
+use std::fmt::{self, Display, Formatter};
+use std::fs::{copy, create_dir_all, remove_file, File, OpenOptions};
+use std::io::{stdin, stdout, ErrorKind, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[throws]
 fn main() {
-    let matches = App::new(crate_name!())
+    if let Err(error) = release() {
+        eprintln!("Error: {:?}", error);
+        let code = if error.is::<NothingToRelease>() {
+            NOTHING_TO_RELEASE
+        } else {
+            1
+        };
+        process::exit(code);
+    }
+}
+
+/// The command line.
+fn app() -> App<'static, 'static> {
+    App::new(crate_name!())
         .version(crate_version!())
         .about("Opinionated automated release actions for Rust projects.")
         .args(&[
@@ -24,6 +41,10 @@ fn main() {
                 .long("major")
                 .help("Release is a new major version (X.y.z). Default: new minor version.")
                 .conflicts_with("patch"),
+            Arg::with_name("prerelease")
+                .long("prerelease")
+                .help("Release is the next prerelease (x.y.z-rc.N+1) of the latest one.")
+                .conflicts_with_all(&["patch", "major", "strip-dev-on-base"]),
             Arg::with_name("path")
                 .short("r")
                 .long("repo")
@@ -38,233 +59,4323 @@ fn main() {
                 .short("f")
                 .long("for")
                 .takes_value(true)
-                .help("Use this version as the base (X or X.Y). Default: latest.")
+                .help("Use the latest version of this line as the base (X or X.Y). Default: latest.")
                 .conflicts_with("major"),
+            Arg::with_name("base-req")
+                .long("for-req")
+                .takes_value(true)
+                .value_name("constraint")
+                .help("Use the latest version matching this semver constraint as the base.")
+                .conflicts_with("base"),
             Arg::with_name("install")
                 .short("i")
                 .long("install")
                 .help("Install the new version locally."),
+            Arg::with_name("install-args")
+                .long("install-args")
+                .takes_value(true)
+                .value_name("args")
+                .default_value("--force")
+                .allow_hyphen_values(true)
+                .help("More arguments for `cargo install`, replacing the default."),
             Arg::with_name("no-push")
                 .short("n")
                 .long("no-push")
                 .help("Do not perform a final push to the remote."),
+            Arg::with_name("tag-template")
+                .long("tag-template")
+                .takes_value(true)
+                .help("Tag name, `{version}` being substituted. Default: v{version}."),
+            Arg::with_name("tag-filter")
+                .long("tag-filter")
+                .takes_value(true)
+                .value_name("regex")
+                .help("Discover the tags matching this regex, its first group being the version."),
+            Arg::with_name("dev-style")
+                .long("dev-style")
+                .takes_value(true)
+                .possible_values(&["next", "restore"])
+                .help("Post-release version: next minor or released version, with -dev. Default: next."),
+            Arg::with_name("fresh-verify")
+                .long("fresh-verify")
+                .help("Also run the gates on a pristine checkout of the release commit."),
+            Arg::with_name("commit-paths")
+                .long("commit-paths")
+                .help("Only commit the files rslease edits. Default: all tracked changes."),
+            Arg::with_name("commit-lock-separately")
+                .long("commit-lock-separately")
+                .help("Commit the Cargo.lock changes on their own, before the release commit.")
+                .conflicts_with("no-commit"),
+            Arg::with_name("sign")
+                .long("sign")
+                .help("Create a signed annotated tag."),
+            Arg::with_name("sign-commits")
+                .long("sign-commits")
+                .help("Sign the release and post-release commits."),
+            Arg::with_name("signoff")
+                .long("signoff")
+                .help("Add a Signed-off-by trailer to the release and post-release commits."),
+            Arg::with_name("signing-key")
+                .long("signing-key")
+                .takes_value(true)
+                .help("Sign with this key instead of git's default user.signingkey."),
+            Arg::with_name("gpg-program")
+                .long("gpg-program")
+                .takes_value(true)
+                .value_name("path")
+                .help("Sign with this program instead of git's default gpg.program."),
+            Arg::with_name("respect-signing-config")
+                .long("respect-signing-config")
+                .help("Sign as git is configured to: tag.gpgsign, commit.gpgsign, or a key."),
+            Arg::with_name("review-lock")
+                .long("review-lock")
+                .help("Print the dependency changes of `cargo update`, asking for confirmation."),
+            Arg::with_name("parallel-gates")
+                .long("parallel-gates")
+                .help("Run the read-only gates concurrently, each with its own target directory."),
+            Arg::with_name("pre-bump-command")
+                .long("pre-bump-command")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Run this shell command before computing the version. Repeatable."),
+            Arg::with_name("post-bump-command")
+                .long("post-bump-command")
+                .takes_value(true)
+                .value_name("command")
+                .help("Run this shell command after the bump, to update version-derived files."),
+            Arg::with_name("fetch-tags")
+                .long("fetch-tags")
+                .conflicts_with("local")
+                .help("Fetch the tags even with --no-push, which otherwise skips fetching."),
+            Arg::with_name("include-remote-tags")
+                .long("include-remote-tags")
+                .help("Also consider the tags of the remote, fetched or not.")
+                .conflicts_with("local"),
+            Arg::with_name("strip-dev-on-base")
+                .long("strip-dev-on-base")
+                .help("Release the -dev version of Cargo.toml, stripped (x.y.z-dev → x.y.z).")
+                .conflicts_with_all(&["patch", "major"]),
+            Arg::with_name("verify-base")
+                .long("verify-base")
+                .help("Check that Cargo.toml is at the base tag version, or its -dev successor."),
+            Arg::with_name("workspace")
+                .long("workspace")
+                .help("Release a workspace sharing the version of [workspace.package], see below.")
+                .conflicts_with("install"),
+            Arg::with_name("locked")
+                .long("locked")
+                .help("Pass --locked to cargo, keeping dependencies as in Cargo.lock.")
+                .conflicts_with("review-lock"),
+            Arg::with_name("scheme")
+                .long("scheme")
+                .takes_value(true)
+                .possible_values(&["semver", "calver"])
+                .help("Versioning scheme, calver being YYYY.M.N. Default: semver."),
+            Arg::with_name("skip-version")
+                .long("skip-version")
+                .takes_value(true)
+                .value_name("version")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Never release this reserved version. Repeatable."),
+            Arg::with_name("skip-reserved-strategy")
+                .long("skip-reserved-strategy")
+                .takes_value(true)
+                .possible_values(&["fail", "bump"])
+                .requires("skip-version")
+                .help("When the new version is reserved, stop or bump again. Default: fail."),
+            Arg::with_name("zerover")
+                .long("zerover")
+                .help("Below 1.0.0, bump the minor for --major and the patch by default."),
+            Arg::with_name("set-version")
+                .long("set-version")
+                .takes_value(true)
+                .value_name("version")
+                .conflicts_with_all(&[
+                    "patch",
+                    "major",
+                    "prerelease",
+                    "scheme",
+                    "strip-dev-on-base",
+                ])
+                .help("Release exactly this version. Default: RSLEASE_VERSION, if set."),
+            Arg::with_name("increment")
+                .long("increment")
+                .takes_value(true)
+                .value_name("expression")
+                .conflicts_with_all(&[
+                    "patch",
+                    "major",
+                    "prerelease",
+                    "scheme",
+                    "set-version",
+                    "strip-dev-on-base",
+                    "zerover",
+                ])
+                .help("Compute the version from the base one, e.g. 'minor+1,patch=0,pre=rc.1'."),
+            Arg::with_name("no-commit")
+                .long("no-commit")
+                .help("Stop before committing, leaving the bump for review.")
+                .conflicts_with_all(&["fresh-verify", "install"]),
+            Arg::with_name("no-tag")
+                .long("no-tag")
+                .help("Commit the release without tagging it, nor pushing a tag.")
+                .conflicts_with_all(&["tag-existing", "sign"]),
+            Arg::with_name("require-merged")
+                .long("require-merged")
+                .help("Check that HEAD is on the default branch of the remote."),
+            Arg::with_name("skip-missing-components")
+                .long("skip-missing-components")
+                .help("Skip `clippy` or `fmt`, with a warning, if not installed."),
+            Arg::with_name("build-from-git")
+                .long("build-from-git")
+                .help("Add the commit hash as build metadata (+sha.<hash>), in Cargo.toml only."),
+            Arg::with_name("author")
+                .long("author")
+                .takes_value(true)
+                .help("Commit and tag as `Name <email>`. Default: git config identity."),
+            Arg::with_name("allow-branch")
+                .long("allow-branch")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only release from branches matching this glob. Repeatable. Default: any."),
+            Arg::with_name("tag-existing")
+                .long("tag-existing")
+                .help("Recovery: only tag (and push) HEAD, with the version of its Cargo.toml.")
+                .conflicts_with_all(&["dry-run", "no-commit"]),
+            Arg::with_name("tag-only-no-commit")
+                .long("tag-only-no-commit")
+                .help("Only tag HEAD with the next version, without editing Cargo.toml.")
+                .conflicts_with_all(&[
+                    "tag-existing",
+                    "no-tag",
+                    "no-commit",
+                    "dry-run",
+                    "resume",
+                    "strip-dev-on-base",
+                    "verify-base",
+                    "build-from-git",
+                    "install",
+                ]),
+            Arg::with_name("force-tag")
+                .long("force-tag")
+                .help("With --tag-existing, replace the tag if it exists, locally and remotely."),
+            Arg::with_name("resign-tag")
+                .long("resign-tag")
+                .takes_value(true)
+                .value_name("tag")
+                .help("Recovery: recreate this tag signed, at the same commit, with --yes.")
+                .conflicts_with_all(&[
+                    "tag-existing",
+                    "tag-only-no-commit",
+                    "list-tags",
+                    "prune-dev-tags",
+                    "plan",
+                    "resume",
+                ]),
+            Arg::with_name("dirty-allow")
+                .long("dirty-allow")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Ignore changes to this pathspec in the clean check. Repeatable."),
+            Arg::with_name("max-commits-since-tag")
+                .long("max-commits-since-tag")
+                .takes_value(true)
+                .help("Stop if there are more commits than this since the base tag."),
+            Arg::with_name("min-commits-since-tag")
+                .long("min-commits-since-tag")
+                .takes_value(true)
+                .help("Stop if there are less commits than this since the base tag."),
+            Arg::with_name("require-changelog-entries")
+                .long("require-changelog-entries")
+                .help("Check that the changelog has entries under [Unreleased]."),
+            Arg::with_name("changelog-file")
+                .long("changelog-file")
+                .takes_value(true)
+                .value_name("path")
+                .default_value("CHANGELOG.md")
+                .help("The changelog, relative to --repo."),
+            Arg::with_name("keepachangelog")
+                .long("keepachangelog")
+                .help("Rename [Unreleased] of the changelog to the release, see below."),
+            Arg::with_name("tag-body-from-changelog")
+                .long("tag-body-from-changelog")
+                .help("Annotate the release tag with the changelog section of the release.")
+                .conflicts_with("no-tag"),
+            Arg::with_name("allow-empty-release")
+                .long("allow-empty-release")
+                .help("Release even if nothing was committed since the latest tag."),
+            Arg::with_name("yes")
+                .long("yes")
+                .help("Go on when a guard stops the release, with a warning."),
+            Arg::with_name("plan")
+                .long("plan")
+                .help("Print the steps and commands of the release as JSON, running nothing."),
+            Arg::with_name("dump-commands")
+                .long("dump-commands")
+                .requires("plan")
+                .help("With --plan, print the commands as shell lines instead of JSON."),
+            Arg::with_name("publish-docs")
+                .long("publish-docs")
+                .help("After pushing, push the rustdoc of the release to --docs-branch."),
+            Arg::with_name("docs-branch")
+                .long("docs-branch")
+                .takes_value(true)
+                .value_name("branch")
+                .default_value("gh-pages")
+                .help("The branch of the remote that --publish-docs pushes to."),
+            Arg::with_name("github-output")
+                .long("github-output")
+                .help("Write the versions and tag to $GITHUB_OUTPUT, if set, for GitHub Actions."),
+            Arg::with_name("verify-reachable")
+                .long("verify-reachable")
+                .help("After pushing, warn if the tag is not on the remote default branch.")
+                .conflicts_with_all(&["no-push", "local"]),
+            Arg::with_name("strict-clippy")
+                .long("strict-clippy")
+                .help("Fail the clippy gate on any output, not only on denied lints."),
+            Arg::with_name("build")
+                .long("build")
+                .help("Also gate on `cargo build --release`."),
+            Arg::with_name("build-all-features")
+                .long("build-all-features")
+                .help("Build with --all-features for the --build gate.")
+                .requires("build"),
+            Arg::with_name("feature-matrix")
+                .long("feature-matrix")
+                .help("Also gate on `cargo check` for a matrix of feature combinations."),
+            Arg::with_name("feature-combination")
+                .long("feature-combination")
+                .takes_value(true)
+                .value_name("features")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("feature-matrix")
+                .help("Check these comma-separated features instead of the default matrix."),
+            Arg::with_name("resume")
+                .long("resume")
+                .help("Go on with the interrupted release, skipping the steps it completed.")
+                .conflicts_with_all(&["plan", "dry-run", "tag-existing"]),
+            Arg::with_name("reset-state")
+                .long("reset-state")
+                .help("Forget about an interrupted release and start over.")
+                .conflicts_with("resume"),
+            Arg::with_name("push-option")
+                .long("push-option")
+                .takes_value(true)
+                .value_name("option")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Pass `-o <option>` to every `git push`, e.g. ci.skip."),
+            Arg::with_name("close-issue")
+                .long("close-issue")
+                .takes_value(true)
+                .value_name("number")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Add `Closes #<number>` to the release commit message. Repeatable."),
+            Arg::with_name("no-verify-clean-after")
+                .long("no-verify-clean-after")
+                .help("Do not check that the tree is clean once everything is committed."),
+            Arg::with_name("backup")
+                .long("backup")
+                .help("Copy each file the release edits to <path>.bak first, see below."),
+            Arg::with_name("keep-backup")
+                .long("keep-backup")
+                .requires("backup")
+                .help("Keep the --backup copies once the release is committed."),
+            Arg::with_name("version-file")
+                .long("version-file")
+                .takes_value(true)
+                .value_name("path")
+                .help("Read and write the version in this file, as a bare version string."),
+            Arg::with_name("version-file-only")
+                .long("version-file-only")
+                .requires("version-file")
+                .help("Leave the version of Cargo.toml alone, see --version-file."),
+            Arg::with_name("expected-crate-name")
+                .long("expected-crate-name")
+                .takes_value(true)
+                .value_name("name")
+                .help("Stop unless the package of Cargo.toml has this name."),
+            Arg::with_name("list-tags")
+                .long("list-tags")
+                .help("Print the semver tags found, marking the latest, and exit."),
+            Arg::with_name("prune-dev-tags")
+                .long("prune-dev-tags")
+                .help("Delete the `-dev` prerelease tags, with --yes, and exit.")
+                .conflicts_with_all(&["list-tags", "plan", "resume"]),
+            Arg::with_name("show-current")
+                .long("show-current")
+                .help("Print the version in Cargo.toml and exit."),
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Stop after computing the version, printing the Cargo.toml change."),
+            Arg::with_name("check-releasable")
+                .long("check-releasable")
+                .help("With --dry-run, exit with 10 when there is nothing to release, see below.")
+                .requires("dry-run"),
+            Arg::with_name("no-post-release")
+                .long("no-post-release")
+                .alias("single-commit")
+                .help("Only make the release commit, without the post-release `-dev` bump.")
+                .conflicts_with_all(&["dev-style", "post-version-bump"]),
+            Arg::with_name("post-version-bump")
+                .long("post-version-bump")
+                .takes_value(true)
+                .possible_values(&["major", "minor", "patch"])
+                .help("Post-release version increment, for --dev-style next. Default: minor."),
+            Arg::with_name("local")
+                .long("local")
+                .help("Offline mode: no network interaction at all. Implies --no-push."),
         ])
         .after_help(
             "\
         This program performs the following actions:\n\
         + In --repo, by default the current directory.\n\
+        + If --expected-crate-name, check that it is the `name` of the [package] of\n\
+        Cargo.toml.\n\
+        + If --show-current, print the version of Cargo.toml, as it is read for the\n\
+        release, and stop there.\n\
+        + If --list-tags, print the semver tags found as in the tag discovery below, oldest\n\
+        first and within --for or --for-req if given, mark the one a release would be\n\
+        based on as latest, and stop there.\n\
+        + If --prune-dev-tags, list the tags of --tag-template (or --tag-filter) whose\n\
+        version is a development prerelease, with a `dev` identifier as in 1.3.0-dev, also\n\
+        on the remote with --include-remote-tags unless --no-push, then delete them if\n\
+        --yes, or only print them with --dry-run, and stop there. Other prereleases, e.g.\n\
+        1.3.0-rc.1, are published ones that --prerelease counts from, and are kept. This is\n\
+        maintenance, unrelated to any release.\n\
+        + If --resign-tag, which requires --yes, replace the tag with a signed annotated one\n\
+        at the same commit, keeping its message if it had one, check it with `git tag -v`,\n\
+        force-push it with --force-tag unless --no-push, and stop there.\n\
         + If --branch is specified, checkout the commit.\n\
-        + Check if repo is clean and up to date: `git status`, `git rev-list`.\n\
-        + Retrieve the latest semver tag from git, possibly coerced by --for.\n\
+        + If --allow-branch is specified, check that the current branch matches one of the\n\
+        globs (`*` does not match `/`, `**` does).\n\
+        + Check if repo is clean and up to date: `git status`, `git fetch --tags`,\n\
+        `git rev-list`. Paths given with --dirty-allow are excluded from `git status`.\n\
+        Without pushing there is no fetch, unless --fetch-tags. A shallow clone is only\n\
+        warned about.\n\
+        + If --require-merged, check that HEAD is an ancestor of the remote default branch.\n\
+        + If --tag-existing, tag HEAD with the version of its Cargo.toml, push the tag\n\
+        unless --no-push, and stop there.\n\
+        + If --require-changelog-entries, check that --changelog-file, CHANGELOG.md by\n\
+        default, has a `## [Unreleased]` section with something else than headings, as in\n\
+        https://keepachangelog.com. With --keepachangelog, the section must exist, but may\n\
+        be empty.\n\
+        + Run each --pre-bump-command, in order. The new version is not known yet.\n\
+        + Retrieve the latest semver tag from git, possibly coerced by --for, or by\n\
+        --for-req with any semver constraint, e.g. '>=1.2, <1.5'. The tags are\n\
+        those of --tag-template unless --tag-filter, e.g. '^cli-v(\\d+\\.\\d+\\.\\d+)$', whose\n\
+        first group must be a semver version, replaces it. New tags still follow\n\
+        --tag-template. With --include-remote-tags, the tags of the remote are included:\n\
+        `git ls-remote`.\n\
+        + Unless --allow-empty-release, count the commits since that tag, not counting the\n\
+        post-release commit, and stop if there are none: the release would only bump the\n\
+        version.\n\
+        + If --max-commits-since-tag or --min-commits-since-tag, count the commits since\n\
+        that tag, not counting the post-release commit, and stop unless --yes.\n\
+        + If --verify-base, check that Cargo.toml has the version of that tag, or the\n\
+        post-release version that follows it.\n\
         + Increase the semver. Defaults to minor, use --patch or --major as needed.\n\
-        + Edit Cargo.toml, replacing `version`.\n\
-        + Run the cargo commands: `update`, `clippy -D warnings`, `fmt`.\n\
-        + Commit and create a new semver tag for the version.\n\
-        + If --install, run `cargo install`.\n\
-        + If a semver tag for the next minor does not already exist:\n\
-        ++ Edit Cargo.toml, replacing `version` with the next minor with '-dev' prerelease.\n\
+        With --zerover, and while the major is 0, --major bumps the minor and the default\n\
+        bumps the patch instead, as 0.x minors are the breaking ones: 0.2.0 → 0.3.0.\n\
+        With --prerelease, prerelease tags are discovered too and the latest one gets its\n\
+        last numeric identifier incremented, 1.2.0-rc.1 → 1.2.0-rc.2; there is then no\n\
+        post-release step.\n\
+        With --scheme calver, versions are YYYY.M.N with N counting the releases of the\n\
+        month (UTC), and there is no post-release step.\n\
+        With --set-version, the version is exactly the given one; without any of\n\
+        --set-version, --patch, --major, --prerelease, --scheme calver and\n\
+        --strip-dev-on-base, the RSLEASE_VERSION environment variable, if set and not empty,\n\
+        is used the same way. Either way, releasing a version that has a tag is an error.\n\
+        With --increment, the version is the base one transformed by a comma-separated\n\
+        list of operations, applied in order: `<field>+<n>` adds n to and `<field>=<n>`\n\
+        sets major, minor or patch, `pre=<identifiers>` sets the prerelease and `pre=`\n\
+        removes it. Nothing is reset implicitly, so 'minor+1,patch=0,pre=rc.1' turns 1.2.3\n\
+        into 1.3.0-rc.1 while 'minor+1' alone makes 1.3.3. There is no `build` field, as\n\
+        tag names cannot carry build metadata, and the result must be above the base.\n\
+        With --strip-dev-on-base, the version is instead the one in Cargo.toml, which must\n\
+        be a '-dev' prerelease, without that prerelease: 1.3.0-dev is released as 1.3.0.\n\
+        A version given with --skip-version is reserved: releasing it is an error, unless\n\
+        --skip-reserved-strategy bump, where a computed version is increased again, with a\n\
+        warning, until it is not reserved: from 1.2.x, with 1.3.0 reserved, the minor\n\
+        release is 1.4.0. An exact or --strip-dev-on-base version is never increased.\n\
+        + If --dry-run, print the diff of Cargo.toml and stop there. With\n\
+        --check-releasable, the exit code then tells whether there is anything to release:\n\
+        0 if so, 10 if not, as there are no commits since the base tag (see\n\
+        --allow-empty-release) or the new version already has a tag, 1 on any other error.\n\
+        + If --tag-only-no-commit, tag HEAD for the new version, push the tag unless\n\
+        --no-push, and stop there. The version only comes from the tags, Cargo.toml is\n\
+        neither read nor edited.\n\
+        + If --backup, copy each file the release edits, Cargo.toml, Cargo.lock,\n\
+        --version-file and the changelog with --keepachangelog, to <path>.bak, with a\n\
+        warning for each copy that git does not ignore. The copies are never committed,\n\
+        nor seen by the clean tree checks, and are removed once the release is committed\n\
+        unless --keep-backup. They are there to restore by hand, rslease never does.\n\
+        + Edit Cargo.toml, replacing `version`, and print the replaced and new versions\n\
+        (so does the post-release edit). With --build-from-git, the version there carries\n\
+        the short hash of the released-from commit as build metadata, e.g.\n\
+        1.2.3+sha.abc1234; tag names never carry build metadata.\n\
+        + Run the cargo commands: `update`, `clippy -D warnings`, `fmt`. The gates, between\n\
+        `update` and `fmt`, also include `build --release` with --build, and `check` with\n\
+        --feature-matrix: without default features, with all features, and with each\n\
+        feature of the manifest alone, unless --feature-combination picks the lists of\n\
+        features to check (an empty list checks without any feature). With --locked,\n\
+        `update --workspace` only refreshes the crate's own version in Cargo.lock, and\n\
+        `--locked` is passed to `clippy` and `install`. With --strict-clippy, `clippy` runs\n\
+        with `--quiet` and fails on any output even if it succeeds, which is very strict:\n\
+        it is mostly for codebases without a single warning, including from build scripts.\n\
+        With --parallel-gates, the gates run concurrently, each but the first with its own\n\
+        target directory, `rslease-gate-N` in the usual one, as cargo locks the directory\n\
+        it builds in: these are built from scratch the first time, then reused.\n\
+        + If --review-lock, print the Cargo.lock changes of `update` and, when interactive,\n\
+        ask for confirmation before going on.\n\
+        + If --keepachangelog, rename the `## [Unreleased]` heading of the changelog to\n\
+        `## [x.y.z] - YYYY-MM-DD` (UTC), insert a new empty `## [Unreleased]` above it, and\n\
+        if the `[Unreleased]: .../compare/<tag>...HEAD` link exists, make it compare from\n\
+        the new tag and add the link of the release.\n\
+        + Run --post-bump-command, with the new version in RSLEASE_NEW_VERSION. The files\n\
+        it modifies are part of the release commit if they are tracked, and unless\n\
+        --commit-paths.\n\
+        + If --no-commit, stop there, leaving the changes in the working tree.\n\
+        + Commit the release, with a `Closes #n` line per --close-issue at the end of the\n\
+        message. With --commit-paths, only Cargo.toml and Cargo.lock are staged rather\n\
+        than all tracked changes, so e.g. `cargo fmt` edits are left out. For a crate in a\n\
+        subdirectory of the repository, Cargo.lock is the nearest one, up to the root, as\n\
+        the one of a workspace is.\n\
+        With --commit-lock-separately, Cargo.lock is first committed alone, if it changed,\n\
+        as \"Update Cargo.lock.\", so that the release commit does not touch it while the\n\
+        tag still has the updated lockfile. The post-release commit is not split.\n\
+        + If --fresh-verify, run `clippy -D warnings` again on a temporary worktree.\n\
+        + Create a new semver tag for the version, unless --no-tag. With\n\
+        --tag-body-from-changelog, the tag is annotated, its message followed by the\n\
+        section of the version in the changelog, e.g. the one --keepachangelog made, given\n\
+        to `git tag -F -`. Without such a section, the tag is made as usual, with a warning.\n\
+        + Check that the Cargo.toml of the tag (or commit) has the released version.\n\
+        + The commits and tag are signed with --sign-commits and --sign respectively, using\n\
+        --signing-key and --gpg-program if given. With --respect-signing-config, they also\n\
+        are when git is configured to sign them, with commit.gpgsign and tag.gpgsign, or\n\
+        when git has a user.signingkey.\n\
+        + With --signoff, the commits also get a `Signed-off-by` trailer, from `git commit\n\
+        -s`, after any --close-issue line. This is a sign-off, not a signature.\n\
+        + If --install, run `cargo install`, with --install-args.\n\
+        + Unless this is a maintenance release (--patch with --for or --for-req), the\n\
+        release is a prerelease, or --no-post-release (or its alias --single-commit) is\n\
+        given, and if a semver tag for the next minor (or --post-version-bump) does not\n\
+        already exist (always with --dev-style restore):\n\
+        ++ Edit Cargo.toml, replacing `version` with the next minor (or --post-version-bump)\n\
+        with '-dev' prerelease, or the released version with '-dev' prerelease for\n\
+        --dev-style restore.\n\
         ++ Run `cargo update` again.\n\
-        ++ Commit.\n\
-        + Unless --no-push, push the new HEAD, then push the new tag.\n\
+        ++ Commit. Hence two commits per release: the tag points at the released version,\n\
+        while the branch moves on to the next development version.\n\
+        + Check again that the repo is clean, as in the first check, since a step leaving\n\
+        changes uncommitted is a bug. Not with --commit-paths, nor with\n\
+        --no-verify-clean-after.\n\
+        + Unless --no-push, push the new HEAD, then push the new tag if any. Each\n\
+        --push-option is given to both pushes.\n\
+        + If --publish-docs, and unless --no-push, run `cargo doc --no-deps` on a temporary\n\
+        worktree of the release, and push the docs as a new commit of --docs-branch,\n\
+        gh-pages by default, on top of the remote one if it exists. The commit is made\n\
+        with a temporary index, so no local branch is created nor checked out. With\n\
+        --workspace, every member is documented, and the index of the docs redirects to\n\
+        the root package, if any.\n\
+        + If --verify-reachable, fetch again and warn if the tag is not an ancestor of the\n\
+        remote default branch.\n\
+        + Print the release commit and the commit the tag points to.\n\
+        + If --github-output and GITHUB_OUTPUT is set, as in GitHub Actions, append the\n\
+        `new_version`, `tag` and `previous_version` (of the base tag) outputs to that file.\n\
+        `tag` is empty with --no-tag. --tag-only-no-commit writes them too.\n\
+        \n\
+        With --version-file, the version is read from that file instead of Cargo.toml when\n\
+        it exists, and wherever Cargo.toml gets a version the file gets it too, created if\n\
+        needed and keeping its trailing newline or lack thereof. With --version-file-only,\n\
+        Cargo.toml is not edited at all, e.g. when a build script reads the file.\n\
+        \n\
+        From the manifest update on, the progress is saved to .git/rslease-state, which is\n\
+        removed once the release is done. While it exists, other releases are refused: after\n\
+        fixing whatever failed, rerun with --resume and the same options to go on from the\n\
+        failed step, with the same versions and without the checks before the manifest\n\
+        update, or use --reset-state to start over.\n\
+        \n\
+        In a bare or mirror clone, only --tag-existing, --tag-only-no-commit, --show-current,\n\
+        --list-tags and --prune-dev-tags are available, reading Cargo.toml from HEAD, and\n\
+        there is no clean check nor upstream check.\n\
+        \n\
+        --local is the offline mode, meant for experimenting on a throwaway repo: there is\n\
+        no fetch, no upstream check and no push.\n\
+        \n\
+        --workspace releases a workspace whose members inherit its version, with\n\
+        `version.workspace = true`, from --repo, its root: Cargo.toml is the root manifest,\n\
+        whose [workspace.package] version is the one edited, the gates and `fmt` cover the\n\
+        whole workspace, with `--workspace` (`--all` for `fmt`), and there is one tag for\n\
+        all the members.\n\
         \n\
         WARNING: Cargo.toml is naively edited using regexps. Most importantly, the first\n\
-        occurrence of `^version = ..$` must belong to [package]. See the v1 for safe parsing,\n\
-        which sadly came with too many caveats.\n\
+        occurrence of `^version = ..$` must belong to [package], or to [workspace.package]\n\
+        with --workspace, which is checked. See the v1 for safe parsing, which sadly came\n\
+        with too many caveats.\n\
         ",
         )
-        .get_matches();
-    let release = if matches.is_present("patch") {
-        Patch
-    } else if matches.is_present("major") {
-        Major
-    } else {
-        Minor
-    };
+}
+
+#[throws]
+fn release() {
+    let matches = app().get_matches();
     if let Some(path) = matches.value_of("path") {
         set_current_dir(path)?;
     }
-    let branch = matches.value_of("commit");
-    let constraint = {
-        if let Some(base) = matches.value_of("base") {
-            if !Regex::new(r"\d+(\.\d+)?")?.is_match(base) {
-                bail!("--for: invalid format, should be `X` or `X.Y`.")
-            }
-            if !matches.is_present("patch") && Regex::new(r"\d+\.\d+")?.is_match(base) {
-                bail!("--for: when specifying a minor version (x.Y), `patch` is mandatory.")
-            }
-            VersionReq::parse(&format!("~{}.0", base))?
+    let options = Options::new(&matches)?;
+    check_manifest(&options)?;
+    if matches.is_present("show-current") {
+        if options.bare {
+            println!("{}", committed_version("HEAD", options.version_file)?);
         } else {
-            VersionReq::any()
+            println!("{}", current_version(options.version_file)?);
         }
-    };
-    let no_push = matches.is_present("no-push");
+        return;
+    }
 
-    if let Some(branch) = branch {
-        Command::new("git")
-            .args(&["checkout", branch])
-            .output_success()
-            .context(format!("Failed to checkout branch {}", branch))?;
+    if matches.is_present("list-tags") {
+        list_tags(&options)?;
+        return;
     }
-    let install = matches.is_present("install");
 
-    Command::new("git")
-        .args(&["status", "--porcelain=v2"])
-        .empty_stdout()
-        .context("`git status` not empty; repo not clean")?;
+    if matches.is_present("force-tag")
+        && !matches.is_present("tag-existing")
+        && !matches.is_present("resign-tag")
+    {
+        bail!("--force-tag: requires --tag-existing or --resign-tag.");
+    }
 
-    if !no_push {
-        Command::new("git")
-            .arg("fetch")
-            .output_success()
-            .context("Failed to fetch upstream")?;
+    if let Some(tag) = matches.value_of("resign-tag") {
+        resign_tag(&options, tag)?;
+        return;
+    }
 
-        Command::new("git")
-            .args(&["rev-list", "HEAD..HEAD@{upstream}"])
-            .empty_stdout()
-            .context("`git rev-list` not empty; repo behind upstream")?;
+    if matches.is_present("prune-dev-tags") {
+        prune_dev_tags(&options)?;
+        return;
     }
 
-    let out = Command::new("git")
-        .args(&["tag", "--list"])
-        .output_success()?;
-    let stdout = String::from_utf8(out.stdout)?.trim().to_owned();
-    let mut semver_tags = vec![];
-    let semver_tag_re = Regex::new(r"^v\d+.\d+.\d+$")?;
-    for line in stdout.lines() {
-        if !semver_tag_re.is_match(line) {
-            continue;
+    if matches.is_present("plan") {
+        let plan = plan(&options)?;
+        if matches.is_present("dump-commands") {
+            dump_commands(&plan);
+        } else {
+            print_plan(&plan);
         }
-        semver_tags.push(Version::parse(&line[1..])?);
+        return;
     }
-    let semver_tags = semver_tags;
-    let latest = {
-        if let Some(v) = semver_tags.iter().filter(|v| constraint.matches(v)).max() {
-            v.clone()
-        } else {
-            bail!(
-                "No matching semver tag found for constraint {}.",
-                constraint
-            )
+
+    let state_path = ReleaseState::path().context(Step::Resume)?;
+    if matches.is_present("reset-state") {
+        ReleaseState::clear(&state_path).context(Step::Resume)?;
+    }
+    let resumed = ReleaseState::load(&state_path).context(Step::Resume)?;
+    if resumed.is_some() && !matches.is_present("resume") {
+        bail!(anyhow!(
+            "A release is in progress, see {}. Use --resume or --reset-state.",
+            state_path.display()
+        )
+        .context(Step::Resume));
+    }
+    let (new_version, manifest_version, post_version, base_version) = match &resumed {
+        Some(state) => (
+            state.new_version.clone(),
+            state.manifest_version.clone(),
+            state.post_version.clone(),
+            state.base_version.clone(),
+        ),
+        None => {
+            if matches.is_present("resume") {
+                bail!(anyhow!("No release to resume.").context(Step::Resume));
+            }
+            check_repository(&options)?;
+
+            if matches.is_present("tag-existing") {
+                tag_existing(&options)?;
+                return;
+            }
+
+            check_changelog(&options)?;
+
+            for command in matches.values_of("pre-bump-command").into_iter().flatten() {
+                shell(command)
+                    .output_success()
+                    .context(format!("`{}` failed", command))
+                    .context(Step::PreBumpCommand)?;
+            }
+
+            let (new_version, manifest_version, post_version, latest) = versions(&options)?;
+
+            if options.dry_run {
+                print_dry_run(
+                    &options,
+                    &new_version,
+                    &manifest_version,
+                    post_version.as_ref(),
+                )?;
+                return;
+            }
+
+            if matches.is_present("tag-only-no-commit") {
+                tag_only(&options, &new_version, &latest)?;
+                return;
+            }
+
+            (new_version, manifest_version, post_version, latest)
         }
     };
+    let new_tag = options.tag_template.name(&new_version);
+    let mut state = resumed.unwrap_or(ReleaseState {
+        path: state_path,
+        new_version: new_version.clone(),
+        manifest_version: manifest_version.clone(),
+        post_version: post_version.clone(),
+        base_version: base_version.clone(),
+        release_commit: None,
+        done: vec![],
+    });
 
-    let mut new_version = latest;
-    match release {
-        Major => new_version.increment_major(),
-        Minor => new_version.increment_minor(),
-        Patch => new_version.increment_patch(),
-    };
-    let new_version = new_version;
+    // A workspace member has the Cargo.lock of its workspace, in a parent directory.
+    let lock = lock_path()?;
+    let commit_paths = commit_paths(&options, &lock)?;
 
-    if semver_tags.contains(&new_version) {
-        bail!(
-            "Attempting to release a version that already exists: {}",
-            new_version
-        );
+    if !state.is_done(Step::Bump) {
+        bump(&options, &mut state)?;
+    }
+
+    let message = format!("Release version {}.", new_version);
+    let commit_message = format!("{}{}", message, closing_lines(&options.closed_issues));
+    if !state.is_done(Step::ReleaseCommit) {
+        options.gates.run(Path::new("."))?;
+
+        cargo_tool(
+            &fmt_args(options.workspace),
+            Path::new("."),
+            options.skip_missing,
+        )
+        .context(Step::CargoFmt)?;
+
+        if matches.is_present("no-commit") {
+            ReleaseState::clear(&state.path).context(Step::Resume)?;
+            println!(
+                "Bumped to {}, changes left uncommitted. To finish:",
+                new_version
+            );
+            println!("    git commit -am \"{}\"", commit_message);
+            if !options.no_tag {
+                println!("    git tag {}", new_tag);
+            }
+            return;
+        }
+        commit(
+            &options,
+            &mut state,
+            &commit_message,
+            commit_paths.as_deref(),
+            &lock,
+        )?;
+    }
+
+    if matches.is_present("fresh-verify") && !state.is_done(Step::FreshVerification) {
+        verify_fresh_checkout(&options.gates).context(Step::FreshVerification)?;
+        state
+            .record(Step::FreshVerification)
+            .context(Step::Resume)?;
     }
 
-    let next_exists = {
-        let mut next = new_version.clone();
-        next.increment_minor();
-        semver_tags.contains(&next)
+    if !options.no_tag && !state.is_done(Step::ReleaseTag) {
+        tag(&options, &mut state, &message)?;
+    }
+    let release_commit = match &state.release_commit {
+        Some(commit) => commit.clone(),
+        None => bail!(anyhow!("The release commit is unknown.").context(Step::Resume)),
+    };
+    // Without a tag, the release is identified by its commit.
+    let release_ref = if options.no_tag {
+        release_commit.clone()
+    } else {
+        new_tag.clone()
+    };
+    verify_release(&options, &release_ref, &manifest_version)?;
+    let tagged_commit = if options.no_tag {
+        None
+    } else {
+        Some(rev_parse(&format!("{}^{{}}", new_tag)).context(Step::ReleaseTag)?)
     };
 
-    update_cargo_toml_version(&new_version)?;
+    if matches.is_present("install") && !state.is_done(Step::CargoInstall) {
+        cargo_install(
+            options.locked,
+            matches.value_of("install-args").unwrap_or_default(),
+        )
+        .output_success()
+        .context(Step::CargoInstall)?;
+        state.record(Step::CargoInstall).context(Step::Resume)?;
+    }
 
-    Command::new("cargo").arg("update").output_success()?;
+    if post_version.is_some() && !state.is_done(Step::PostReleaseCommit) {
+        post_release(&options, &mut state, commit_paths.as_deref())?;
+    }
 
-    Command::new("cargo")
-        .args(&["clippy", "--", "-D", "warnings"])
-        .output_success()?;
+    if !matches.is_present("keep-backup") {
+        remove_backups(&options.backups).context(Step::BackupRemoval)?;
+    }
 
-    Command::new("cargo").arg("fmt").output_success()?;
+    // With --commit-paths, leftovers are expected.
+    if commit_paths.is_none() && !matches.is_present("no-verify-clean-after") {
+        clean_check(matches.values_of("dirty-allow"), &options.backups)?
+            .empty_stdout()
+            .context("`git status` not empty; a step left uncommitted changes")
+            .context(Step::FinalCleanTreeCheck)?;
+    }
 
-    Command::new("git")
-        .args(&[
-            "commit",
-            "-am",
-            &format!("Release version {}.", new_version),
-        ])
-        .output_success()?;
+    if !options.no_push {
+        publish(&options, &mut state, &release_ref)?;
+    }
+    ReleaseState::clear(&state.path).context(Step::Resume)?;
 
-    Command::new("git")
-        .args(&["tag", &format!("v{}", new_version)])
-        .output_success()?;
+    if matches.is_present("verify-reachable") {
+        warn_unreachable(&release_ref);
+    }
 
-    if install {
-        Command::new("cargo")
-            .args(&["install", "--path", "."])
-            .output_success()?;
+    println!("Released {}.", new_version);
+    println!("commit: {}", release_commit);
+    if let Some(tagged_commit) = tagged_commit {
+        println!("tagged_commit: {} ({})", tagged_commit, new_tag);
+    }
+    if matches.is_present("github-output") {
+        let tag = if options.no_tag {
+            None
+        } else {
+            Some(new_tag.as_str())
+        };
+        github_output(&new_version, tag, &base_version)?;
+    }
+}
+
+/// The arguments, parsed and checked once for all the steps of the release.
+struct Options<'a> {
+    matches: &'a ArgMatches<'a>,
+    release: ReleaseType,
+    /// An exact version from --set-version, or RSLEASE_VERSION when the command line
+    /// does not choose how to compute it.
+    explicit_version: Option<Version>,
+    increment: Option<Vec<Increment>>,
+    /// A bare (or mirror) clone has the tags, but no working tree to edit nor build.
+    bare: bool,
+    version_file: Option<&'a str>,
+    edit_manifest: bool,
+    workspace: bool,
+    /// The line of the base version, see --for and --for-req.
+    constraint: VersionReq,
+    /// A patch based on an explicit older line is a maintenance release: development
+    /// happens elsewhere, so never write a `-dev` version onto that line.
+    maintenance: bool,
+    /// The --skip-version versions.
+    reserved: Vec<Version>,
+    bump_reserved: bool,
+    dev_style: DevStyle,
+    /// Without pushing there is no need to be up to date with upstream either, so
+    /// --no-push already keeps the network out; --local is the explicit bundle.
+    no_push: bool,
+    closed_issues: Vec<u64>,
+    authoring: Authoring,
+    tag_template: TagTemplate,
+    dry_run: bool,
+    check_releasable: bool,
+    yes: bool,
+    max_commits: Option<usize>,
+    min_commits: Option<usize>,
+    zerover: bool,
+    changelog_file: &'a str,
+    keepachangelog: bool,
+    no_tag: bool,
+    locked: bool,
+    skip_missing: bool,
+    gates: Gates,
+    /// The files the release edits, with their --backup copies.
+    backups: Vec<(String, String)>,
+}
+
+impl<'a> Options<'a> {
+    #[throws]
+    fn new(matches: &'a ArgMatches<'a>) -> Self {
+        let release = if matches.value_of("scheme") == Some("calver") {
+            for (arg, flag) in &[
+                ("patch", "--patch"),
+                ("major", "--major"),
+                ("base", "--for"),
+                ("base-req", "--for-req"),
+                ("strip-dev-on-base", "--strip-dev-on-base"),
+                ("prerelease", "--prerelease"),
+            ] {
+                if matches.is_present(arg) {
+                    bail!("--scheme calver: cannot be used with {}.", flag);
+                }
+            }
+            Calendar
+        } else if matches.is_present("prerelease") {
+            Prerelease
+        } else if matches.is_present("patch") {
+            Patch
+        } else if matches.is_present("major") {
+            Major
+        } else {
+            Minor
+        };
+        let explicit_version = {
+            let chosen = release != Minor
+                || matches.is_present("strip-dev-on-base")
+                || matches.is_present("increment");
+            let given = match matches.value_of("set-version") {
+                Some(version) => Some(("--set-version", version.to_owned())),
+                None if chosen => None,
+                None => match var("RSLEASE_VERSION") {
+                    Ok(version) if !version.trim().is_empty() => Some(("RSLEASE_VERSION", version)),
+                    Ok(_) | Err(VarError::NotPresent) => None,
+                    Err(e) => bail!(anyhow!(e).context("RSLEASE_VERSION")),
+                },
+            };
+            match given {
+                Some((from, version)) => {
+                    let version = Version::parse(version.trim())
+                        .context(format!("{}: invalid version", from))?;
+                    if !version.build.is_empty() {
+                        bail!(
+                            "{}: tag names cannot carry build metadata, see --build-from-git.",
+                            from
+                        );
+                    }
+                    Some(version)
+                }
+                None => None,
+            }
+        };
+        let increment = matches
+            .value_of("increment")
+            .map(parse_increment)
+            .transpose()?;
+        let bare = is_bare_repository()?;
+        if bare {
+            if !matches.is_present("tag-existing")
+                && !matches.is_present("tag-only-no-commit")
+                && !matches.is_present("show-current")
+                && !matches.is_present("list-tags")
+                && !matches.is_present("prune-dev-tags")
+            {
+                bail!(
+                    "Bare repository: only --tag-existing, --tag-only-no-commit, --show-current, \
+                     --list-tags and --prune-dev-tags work without a working tree."
+                );
+            }
+            for (arg, flag) in &[
+                ("commit", "--branch"),
+                ("require-changelog-entries", "--require-changelog-entries"),
+                ("keepachangelog", "--keepachangelog"),
+            ] {
+                if matches.is_present(arg) {
+                    bail!("Bare repository: cannot be used with {}.", flag);
+                }
+            }
+        }
+        let constraint = {
+            if let Some(base) = matches.value_of("base") {
+                if !Regex::new(r"^\d+(\.\d+)?$")?.is_match(base) {
+                    bail!("--for: invalid format, should be `X` or `X.Y`.")
+                }
+                if !matches.is_present("patch") && Regex::new(r"\d+\.\d+")?.is_match(base) {
+                    bail!("--for: when specifying a minor version (x.Y), `patch` is mandatory.")
+                }
+                // `~X` is the whole X.*.* line, `~X.Y` the X.Y.* one.
+                VersionReq::parse(&format!("~{}", base))?
+            } else if let Some(req) = matches.value_of("base-req") {
+                let req = VersionReq::parse(req)
+                    .map_err(|e| anyhow!("--for-req: invalid constraint `{}`: {}", req, e))?;
+                eprintln!("Base constraint: {}", req);
+                req
+            } else {
+                VersionReq::any()
+            }
+        };
+        let reserved = matches
+            .values_of("skip-version")
+            .into_iter()
+            .flatten()
+            .map(|v| {
+                Version::parse(v.trim()).context(format!("--skip-version: invalid version {}", v))
+            })
+            .collect::<ARes<Vec<_>>>()?;
+        let dev_style = match (
+            matches.value_of("dev-style"),
+            matches.value_of("post-version-bump"),
+        ) {
+            (Some("restore"), Some(_)) => {
+                bail!("--post-version-bump: cannot be used with --dev-style restore.")
+            }
+            (Some("restore"), None) => Restore,
+            (_, Some("major")) => Next(Major),
+            (_, Some("patch")) => Next(Patch),
+            _ => Next(Minor),
+        };
+        // Invalid push options would only show once the release is tagged.
+        push_command(matches.values_of("push-option"))?;
+        let closed_issues = issue_numbers(matches.values_of("close-issue"))?;
+
+        let authoring = {
+            let mut config = vec![];
+            let mut sign_tag = matches.is_present("sign") || matches.is_present("resign-tag");
+            let mut sign_commits = matches.is_present("sign-commits");
+            if matches.is_present("respect-signing-config") {
+                let has_key = git_config(&["user.signingkey"])
+                    .context("--respect-signing-config")?
+                    .is_some();
+                let enabled = |key| -> ARes<_> {
+                    let value =
+                        git_config(&["--type=bool", key]).context("--respect-signing-config")?;
+                    Ok(has_key || value.as_deref() == Some("true"))
+                };
+                sign_tag |= enabled("tag.gpgsign")?;
+                sign_commits |= enabled("commit.gpgsign")?;
+            }
+            if let Some(key) = matches.value_of("signing-key") {
+                if key.trim().is_empty() {
+                    bail!("--signing-key: must not be empty.");
+                }
+                if !sign_tag && !sign_commits {
+                    bail!("--signing-key: requires --sign or --sign-commits.");
+                }
+                config.push(format!("user.signingkey={}", key));
+            }
+            if let Some(program) = matches.value_of("gpg-program") {
+                if program.trim().is_empty() {
+                    bail!("--gpg-program: must not be empty.");
+                }
+                if !sign_tag && !sign_commits {
+                    bail!("--gpg-program: requires --sign or --sign-commits.");
+                }
+                config.push(format!("gpg.program={}", program));
+            }
+            let author = matches.value_of("author");
+            if let Some(author) = author {
+                // The committer (and tagger) too, since the runner may have no identity.
+                let c = Regex::new(r"^([^<>]*[^<>\s])\s*<([^<>\s]+)>$")?
+                    .captures(author)
+                    .ok_or_else(|| anyhow!("--author: expected `Name <email>`."))?;
+                config.push(format!("user.name={}", &c[1]));
+                config.push(format!("user.email={}", &c[2]));
+            }
+            Authoring {
+                config,
+                author: author.map(ToOwned::to_owned),
+                sign_tag,
+                sign_commits,
+                signoff: matches.is_present("signoff"),
+            }
+        };
+        let mut tag_template =
+            TagTemplate::parse(matches.value_of("tag-template").unwrap_or("v{version}"))?;
+        if let Some(filter) = matches.value_of("tag-filter") {
+            tag_template = tag_template.filter(filter)?;
+        }
+
+        let max_commits = matches
+            .value_of("max-commits-since-tag")
+            .map(str::parse::<usize>)
+            .transpose()
+            .context("--max-commits-since-tag: not a number")?;
+        let min_commits = matches
+            .value_of("min-commits-since-tag")
+            .map(str::parse::<usize>)
+            .transpose()
+            .context("--min-commits-since-tag: not a number")?;
+        let workspace = matches.is_present("workspace");
+        let locked = matches.is_present("locked");
+        let skip_missing = matches.is_present("skip-missing-components");
+        let gates = Gates {
+            parallel: matches.is_present("parallel-gates"),
+            locked,
+            skip_missing,
+            build: matches.is_present("build"),
+            build_all_features: matches.is_present("build-all-features"),
+            strict_clippy: matches.is_present("strict-clippy"),
+            workspace,
+            feature_matrix: if matches.is_present("feature-matrix") {
+                feature_matrix(matches.values_of("feature-combination"))?
+            } else {
+                vec![]
+            },
+        };
+        let backups = if bare { vec![] } else { backup_paths(matches)? };
+        Options {
+            matches,
+            release,
+            explicit_version,
+            increment,
+            bare,
+            version_file: matches.value_of("version-file"),
+            edit_manifest: !matches.is_present("version-file-only"),
+            workspace,
+            constraint,
+            maintenance: release == Patch
+                && (matches.is_present("base") || matches.is_present("base-req")),
+            reserved,
+            bump_reserved: matches.value_of("skip-reserved-strategy") == Some("bump"),
+            dev_style,
+            no_push: matches.is_present("no-push") || matches.is_present("local"),
+            closed_issues,
+            authoring,
+            tag_template,
+            dry_run: matches.is_present("dry-run"),
+            check_releasable: matches.is_present("check-releasable"),
+            yes: matches.is_present("yes"),
+            max_commits,
+            min_commits,
+            zerover: matches.is_present("zerover"),
+            changelog_file: matches.value_of("changelog-file").unwrap_or_default(),
+            keepachangelog: matches.is_present("keepachangelog"),
+            no_tag: matches.is_present("no-tag"),
+            locked,
+            skip_missing,
+            gates,
+            backups,
+        }
     }
 
-    if !next_exists {
-        let mut post_version = new_version.clone();
-        post_version.increment_minor();
-        post_version.pre = vec![Identifier::AlphaNumeric("dev".to_owned())];
-        let post_version = post_version;
+    /// Whether a development version follows the release of `new_version`, unknown in a
+    /// plan: not after a maintenance release, a prerelease, a CalVer one, nor with
+    /// --no-post-release.
+    fn develops_after(&self, new_version: Option<&Version>) -> bool {
+        let prerelease = match new_version {
+            Some(version) => version.is_prerelease(),
+            None => {
+                self.release == Prerelease
+                    || self.explicit_version.iter().any(Version::is_prerelease)
+            }
+        };
+        !(self.maintenance
+            || prerelease
+            || self.release == Calendar
+            || self.matches.is_present("no-post-release"))
+    }
 
-        update_cargo_toml_version(&post_version)?;
+    /// The semver tags, prereleases included only for a prerelease; which also tells
+    /// whether an explicit prerelease already exists.
+    #[throws]
+    fn semver_tags(&self) -> Vec<(Version, String)> {
+        semver_tags(
+            &self.tag_template,
+            self.matches.is_present("include-remote-tags"),
+            self.release == Prerelease || self.explicit_version.iter().any(|v| v.is_prerelease()),
+        )?
+    }
+}
 
-        Command::new("cargo").arg("update").output_success()?;
+/// Check that Cargo.toml is the expected one, see --expected-crate-name and --workspace.
+#[throws]
+fn check_manifest(options: &Options) {
+    if let Some(expected) = options.matches.value_of("expected-crate-name") {
+        let manifest = if options.bare {
+            committed_manifest("HEAD")?
+        } else {
+            read_cargo_toml()?
+        };
+        let name = manifest_name(&manifest).context(Step::CrateNameCheck)?;
+        if name != expected {
+            bail!(anyhow!(
+                "Cargo.toml is for crate {}, not {}; wrong directory?",
+                name,
+                expected
+            )
+            .context(Step::CrateNameCheck));
+        }
+    }
+    if options.workspace && !options.bare {
+        check_workspace_version(&read_cargo_toml()?)?;
+    }
+}
 
-        Command::new("git")
-            .args(&["commit", "-am", "Post-release."])
-            .output_success()?;
+/// Print the semver tags of the line, see --list-tags.
+#[throws]
+fn list_tags(options: &Options) {
+    let tags = options.semver_tags().context(Step::TagDiscovery)?;
+    let latest = latest_in_line(&tags, &options.constraint);
+    for tag in tags.iter().filter(|(v, _)| in_line(v, &options.constraint)) {
+        if Some(tag) == latest {
+            println!("{} (latest)", tag.1);
+        } else {
+            println!("{}", tag.1);
+        }
     }
+}
 
-    if !no_push {
-        Command::new("git").arg("push").output_success()?;
+/// Replace `tag` with a signed one, see --resign-tag.
+#[throws]
+fn resign_tag(options: &Options, tag: &str) {
+    let authoring = &options.authoring;
+    if !tag_exists(tag).context(Step::TagSigning)? {
+        bail!(anyhow!("Tag {} does not exist.", tag).context(Step::TagSigning));
+    }
+    if !options.yes {
+        bail!(anyhow!("Use --yes to replace tag {}.", tag).context(Step::TagSigning));
+    }
+    let commit = rev_parse(&format!("{}^{{commit}}", tag)).context(Step::TagSigning)?;
+    let message = match tag_message(tag).context(Step::TagSigning)? {
+        Some(message) => message,
+        None => match committed_version(tag, options.version_file) {
+            Ok(version) => format!("Release version {}.", version),
+            Err(_) => format!("Release {}.", tag),
+        },
+    };
+    authoring
+        .tag_command(tag, &message, true)
+        .arg(&commit)
+        .output_success()
+        .context(Step::TagSigning)?;
+    // With the same --gpg-program as the signature.
+    authoring
+        .git()
+        .args(["tag", "-v", tag])
+        .output_success()
+        .context(Step::TagVerification)?;
+    if !options.no_push && options.matches.is_present("force-tag") {
+        push_command(options.matches.values_of("push-option"))?
+            .args(["origin", "--force", tag])
+            .output_success()
+            .context(Step::TagPush)?;
+    } else if !options.no_push {
+        eprintln!("Warning: {} was not pushed, see --force-tag.", tag);
+    }
+    println!("Signed {} as {}.", commit, tag);
+}
 
+/// Delete the `-dev` tags, see --prune-dev-tags.
+#[throws]
+fn prune_dev_tags(options: &Options) {
+    let matches = options.matches;
+    let local = dev_tags(&options.tag_template, local_tags()?).context(Step::TagPruning)?;
+    let remote = if matches.is_present("include-remote-tags") && !options.no_push {
+        dev_tags(&options.tag_template, remote_tags()?).context(Step::TagPruning)?
+    } else {
+        vec![]
+    };
+    if local.is_empty() && remote.is_empty() {
+        println!("No development tags.");
+        return;
+    }
+    let verb = if options.yes && !options.dry_run {
+        "Deleting"
+    } else {
+        "Would delete"
+    };
+    for name in &local {
+        println!("{} tag {}", verb, name);
+    }
+    for name in &remote {
+        println!("{} remote tag {}", verb, name);
+    }
+    if options.dry_run {
+        return;
+    }
+    if !options.yes {
+        bail!(anyhow!("Use --yes to delete these tags.").context(Step::TagPruning));
+    }
+    if !local.is_empty() {
         Command::new("git")
-            .args(&["push", "origin", &format!("v{}", new_version)])
-            .output_success()?;
+            .args(["tag", "--delete"])
+            .args(&local)
+            .output_success()
+            .context(Step::TagPruning)?;
+    }
+    if !remote.is_empty() {
+        push_command(matches.values_of("push-option"))?
+            .args(["origin", "--delete"])
+            .args(remote.iter().map(|name| format!("refs/tags/{}", name)))
+            .output_success()
+            .context(Step::TagPruning)?;
     }
 }
 
-type AVoid = ARes<()>;
+/// Get on the release branch and check that the repository may be released from:
+/// allowed branch, clean tree, up to date with upstream, merged.
+#[throws]
+fn check_repository(options: &Options) {
+    let matches = options.matches;
+    if let Some(branch) = matches.value_of("commit") {
+        Command::new("git")
+            .args(["checkout", branch])
+            .output_success()
+            .context(format!("Failed to checkout branch {}", branch))
+            .context(Step::Checkout)?;
+    }
 
-trait CommandPropagate {
-    fn output_success(&mut self) -> ARes<Output>;
-    fn empty_stdout(&mut self) -> AVoid;
-}
+    if let Some(patterns) = matches.values_of("allow-branch") {
+        let current = current_branch().context(Step::BranchCheck)?;
+        let mut allowed = false;
+        for pattern in patterns.clone() {
+            allowed |= glob_regex(pattern)?.is_match(&current);
+        }
+        if !allowed {
+            let patterns: Vec<_> = patterns.collect();
+            bail!(anyhow!(
+                "Releasing from {} is not allowed, only from: {}.",
+                current,
+                patterns.join(", ")
+            )
+            .context(Step::BranchCheck));
+        }
+    }
 
-impl CommandPropagate for Command {
-    fn output_success(&mut self) -> ARes<Output> {
-        let output = self.output()?;
-        if !output.status.success() {
-            let stderr = String::from_utf8(output.stderr)?.trim().to_owned();
-            bail!(stderr);
+    if !options.bare {
+        clean_check(matches.values_of("dirty-allow"), &options.backups)?
+            .empty_stdout()
+            .context("`git status` not empty; repo not clean")
+            .context(Step::CleanTreeCheck)?;
+    }
+
+    if !options.no_push || matches.is_present("fetch-tags") {
+        // Tags too, a clone can lack some and the latest one would be wrong.
+        Command::new("git")
+            .args(["fetch", "--tags"])
+            .output_success()
+            .context("Failed to fetch upstream")
+            .context(Step::Fetch)?;
+    }
+    let shallow = Command::new("git")
+        .args(["rev-parse", "--is-shallow-repository"])
+        .output_success()
+        .context(Step::Fetch)?;
+    if shallow.stdout.starts_with(b"true") {
+        eprintln!(
+            "Warning: shallow repository, commits since the latest tag may be \
+             miscounted. Use `git fetch --unshallow` for the full history."
+        );
+    }
+
+    // A bare clone has no upstream to be behind, its branches are the remote's.
+    if !options.no_push && !options.bare {
+        Command::new("git")
+            .args(["rev-list", "HEAD..HEAD@{upstream}"])
+            .empty_stdout()
+            .context("`git rev-list` not empty; repo behind upstream")
+            .context(Step::UpstreamCheck)?;
+    }
+
+    if matches.is_present("require-merged") {
+        let default = default_branch().context(Step::MergedCheck)?;
+        if !is_ancestor("HEAD", &default).context(Step::MergedCheck)? {
+            bail!(
+                anyhow!("HEAD has commits that are not on {}.", default).context(Step::MergedCheck)
+            );
         }
-        Ok(output)
     }
+}
 
-    fn empty_stdout(&mut self) -> AVoid {
-        let output = self.output_success()?;
-        if !output.stdout.is_empty() {
-            let stdout = String::from_utf8(output.stdout)?.trim().to_owned();
-            bail!(anyhow!(stdout).context("Command stdout should be empty"));
+/// Recovery: HEAD already is the release commit, only its tag is missing. See
+/// --tag-existing.
+#[throws]
+fn tag_existing(options: &Options) {
+    let force = options.matches.is_present("force-tag");
+    let mut version =
+        committed_version("HEAD", options.version_file).context(Step::ReleaseVerification)?;
+    if version.pre.contains(&dev_identifier()) {
+        bail!(anyhow!(
+            "HEAD has development version {}, check out the release commit.",
+            version
+        )
+        .context(Step::ReleaseVerification));
+    }
+    version.build.clear();
+    let tag = options.tag_template.name(&version);
+    if !force && tag_exists(&tag).context(Step::ReleaseTag)? {
+        bail!(anyhow!("Tag {} already exists, see --force-tag.", tag).context(Step::ReleaseTag));
+    }
+    options
+        .authoring
+        .tag(&tag, &format!("Release version {}.", version), force)
+        .context(Step::ReleaseTag)?;
+    if !options.no_push {
+        let mut push = push_command(options.matches.values_of("push-option"))?;
+        push.arg("origin");
+        if force {
+            push.arg("--force");
         }
-        Ok(())
+        push.arg(&tag).output_success().context(Step::TagPush)?;
     }
+    println!("Tagged {} as {}.", rev_parse("HEAD")?, tag);
 }
 
-#[derive(Eq, PartialEq)]
-enum ReleaseType {
-    Major,
-    Minor,
-    Patch,
+/// Check the `[Unreleased]` section of the changelog, with --require-changelog-entries
+/// or --keepachangelog.
+#[throws]
+fn check_changelog(options: &Options) {
+    let require_entries = options.matches.is_present("require-changelog-entries");
+    if !require_entries && !options.keepachangelog {
+        return;
+    }
+    let changelog_file = options.changelog_file;
+    let mut changelog = String::new();
+    File::open(changelog_file)
+        .and_then(|mut file| file.read_to_string(&mut changelog))
+        .context(format!("Failed to read {}", changelog_file))
+        .context(Step::ChangelogCheck)?;
+    match unreleased_entries(&changelog).context(Step::ChangelogCheck)? {
+        Some(0) if require_entries => bail!(anyhow!(
+            "The [Unreleased] section of {} has no entries.",
+            changelog_file
+        )
+        .context(Step::ChangelogCheck)),
+        Some(_) => {}
+        None => bail!(anyhow!("{} has no [Unreleased] section.", changelog_file)
+            .context(Step::ChangelogCheck)),
+    }
 }
 
+/// The versions of the release: the new one, the one in the manifest, with any build
+/// metadata, the development one after it, if any, and the base one it follows.
 #[throws]
-fn update_cargo_toml_version(version: &Version) {
-    let mut manifest = String::new();
-    File::open("Cargo.toml")?.read_to_string(&mut manifest)?;
-    let re = Regex::new(r#"(?m)^(version\s*=\s*")[^"]*("\s*)$"#)?;
-    if !re.is_match(&manifest) {
-        bail!("Could extract version from Cargo.toml, see --help for more info.");
+fn versions(options: &Options) -> (Version, Version, Option<Version>, Version) {
+    let matches = options.matches;
+    let semver_tags = options.semver_tags().context(Step::TagDiscovery)?;
+    // The base tag as found, which --tag-filter may name unlike --tag-template.
+    let (latest, base_tag) = {
+        if let Some(tag) = latest_in_line(&semver_tags, &options.constraint) {
+            tag.clone()
+        } else {
+            bail!(
+                no_matching_tag(&semver_tags, &options.constraint, matches.value_of("base"))
+                    .context(Step::TagDiscovery)
+            )
+        }
+    };
+
+    if matches.is_present("verify-base") {
+        let current = current_version(options.version_file).context(Step::BaseVerification)?;
+        let successor = dev_version(&latest, &options.dev_style);
+        if current != latest && current != successor {
+            bail!(anyhow!(
+                "{} version {} does not match the base tag version {} (or {}).",
+                options.version_file.unwrap_or("Cargo.toml"),
+                current,
+                latest,
+                successor
+            )
+            .context(Step::BaseVerification));
+        }
+    }
+
+    check_commits(options, &base_tag)?;
+
+    let new_version = new_version(options, &latest)?;
+    let new_tag = options.tag_template.name(&new_version);
+    // Build metadata cannot be part of a tag name, so it only goes into Cargo.toml.
+    let manifest_version = if matches.is_present("build-from-git") {
+        let out = Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output_success()
+            .context(Step::VersionComputation)?;
+        let hash = String::from_utf8(out.stdout)?.trim().to_owned();
+        let mut manifest_version = new_version.clone();
+        manifest_version.build = vec![
+            Identifier::AlphaNumeric("sha".to_owned()),
+            Identifier::AlphaNumeric(hash),
+        ];
+        manifest_version
+    } else {
+        new_version.clone()
+    };
+
+    Command::new("git")
+        .args(["check-ref-format", &format!("refs/tags/{}", new_tag)])
+        .output_success()
+        .context(format!("Invalid tag name: {}", new_tag))
+        .context(Step::VersionComputation)?;
+
+    if semver_tags.iter().any(|(v, _)| *v == new_version) {
+        bail!(nothing_to_release(
+            options.check_releasable,
+            anyhow!(
+                "Attempting to release a version that already exists: {}",
+                new_version
+            )
+            .context(Step::VersionComputation)
+        ));
+    }
+
+    let post_version = {
+        let post_version = dev_version(&new_version, &options.dev_style);
+        let mut next = post_version.clone();
+        next.pre.clear();
+        let next_exists =
+            options.dev_style != Restore && semver_tags.iter().any(|(v, _)| *v == next);
+        if next_exists || !options.develops_after(Some(&new_version)) {
+            None
+        } else {
+            Some(post_version)
+        }
+    };
+
+    (new_version, manifest_version, post_version, latest)
+}
+
+/// Check the commits since the base tag: that there are some, unless
+/// --allow-empty-release, and --max-commits-since-tag and --min-commits-since-tag.
+#[throws]
+fn check_commits(options: &Options, base_tag: &str) {
+    if !options.matches.is_present("allow-empty-release")
+        && commits_since(base_tag).context(Step::EmptyReleaseCheck)? == 0
+    {
+        bail!(nothing_to_release(
+            options.check_releasable,
+            anyhow!(
+                "No commits since {}, the release would only differ by its version. \
+                 Use --allow-empty-release to release anyway.",
+                base_tag
+            )
+            .context(Step::EmptyReleaseCheck)
+        ));
+    }
+
+    if options.max_commits.is_some() || options.min_commits.is_some() {
+        let count = commits_since(base_tag).context(Step::CommitCountCheck)?;
+        let mut problem = None;
+        if let Some(max) = options.max_commits {
+            if count > max {
+                problem = Some(format!("more than {}", max));
+            }
+        }
+        if let Some(min) = options.min_commits {
+            if count < min {
+                problem = Some(format!("less than {}", min));
+            }
+        }
+        if let Some(problem) = problem {
+            let message = format!("{} commits since {}, {}.", count, base_tag, problem);
+            if !options.yes {
+                bail!(anyhow!("{} Use --yes to release anyway.", message)
+                    .context(Step::CommitCountCheck));
+            }
+            eprintln!("Warning: {}", message);
+        }
+    }
+}
+
+/// The version to release after `latest`, exact or computed, and not reserved.
+#[throws]
+fn new_version(options: &Options, latest: &Version) -> Version {
+    let new_version = if let Some(version) = &options.explicit_version {
+        version.clone()
+    } else if options.matches.is_present("strip-dev-on-base") {
+        dev_base(options.version_file).context(Step::VersionComputation)?
+    } else {
+        // In 0.x, the minor is what breaks and the patch what adds.
+        let release = match options.release {
+            Major if options.zerover && latest.major == 0 => Minor,
+            Minor if options.zerover && latest.major == 0 => Patch,
+            release => release,
+        };
+        let next = |from: &Version| -> ARes<Version> {
+            if let Some(increment) = &options.increment {
+                return apply_increment(from, increment);
+            }
+            let mut version = from.clone();
+            match release {
+                Major => version.increment_major(),
+                Minor => version.increment_minor(),
+                Patch => version.increment_patch(),
+                Calendar => version = calendar_version(from)?,
+                Prerelease => version = prerelease_version(from)?,
+            };
+            Ok(version)
+        };
+        let mut new_version = next(latest)?;
+        while options.bump_reserved && options.reserved.contains(&new_version) {
+            eprintln!("Warning: {} is reserved, skipping it.", new_version);
+            new_version = next(&new_version)?;
+        }
+        new_version
+    };
+    if options.reserved.contains(&new_version) {
+        bail!(anyhow!(
+            "Version {} is reserved, see --skip-version and --skip-reserved-strategy.",
+            new_version
+        )
+        .context(Step::VersionComputation));
+    }
+    new_version
+}
+
+/// Print what the release would be, with the diff of the version files, see --dry-run.
+#[throws]
+fn print_dry_run(
+    options: &Options,
+    new_version: &Version,
+    manifest_version: &Version,
+    post_version: Option<&Version>,
+) {
+    let new_tag = options.tag_template.name(new_version);
+    println!("Would release {} as tag {}.", new_version, new_tag);
+    if options.edit_manifest {
+        let manifest = read_cargo_toml().context(Step::ManifestUpdate)?;
+        let bumped = bump_version(&manifest, manifest_version).context(Step::ManifestUpdate)?;
+        print!("{}", unified_diff("Cargo.toml", &manifest, &bumped));
+    }
+    if let Some(path) = options.version_file {
+        let old = read_version_file(path)
+            .context(Step::ManifestUpdate)?
+            .map_or_else(String::new, |v| format!("{}\n", v));
+        let new = format!("{}\n", manifest_version);
+        print!("{}", unified_diff(path, &old, &new));
+    }
+    if let Some(post_version) = post_version {
+        println!("Would then bump to {} for development.", post_version);
+    }
+}
+
+/// Tag HEAD as `new_version`, see --tag-only-no-commit. The tags are the source of
+/// truth, Cargo.toml is left alone.
+#[throws]
+fn tag_only(options: &Options, new_version: &Version, latest: &Version) {
+    let new_tag = options.tag_template.name(new_version);
+    options
+        .authoring
+        .tag(
+            &new_tag,
+            &format!("Release version {}.", new_version),
+            false,
+        )
+        .context(Step::ReleaseTag)?;
+    if !options.no_push {
+        push_command(options.matches.values_of("push-option"))?
+            .args(["origin", &new_tag])
+            .output_success()
+            .context(Step::TagPush)?;
+    }
+    println!("Tagged {} as {}.", rev_parse("HEAD")?, new_tag);
+    if options.matches.is_present("github-output") {
+        github_output(new_version, Some(&new_tag), latest)?;
+    }
+}
+
+/// The paths the release commits are restricted to, see --commit-paths.
+#[throws]
+fn commit_paths<'a>(options: &Options<'a>, lock: &'a str) -> Option<Vec<&'a str>> {
+    if !options.matches.is_present("commit-paths") {
+        return None;
+    }
+    let mut paths = vec!["Cargo.toml"];
+    if is_tracked(lock)? {
+        paths.push(lock);
+    }
+    if let Some(path) = options.version_file {
+        paths.push(path);
+    }
+    if options.keepachangelog {
+        paths.push(options.changelog_file);
+    }
+    Some(paths)
+}
+
+/// Write the release version, to the manifest, the lockfile and the changelog.
+#[throws]
+fn bump(options: &Options, state: &mut ReleaseState) {
+    let matches = options.matches;
+    let review_lock = matches.is_present("review-lock");
+    state.save().context(Step::Resume)?;
+    // Only once, as on resume the files may be edited already.
+    if !options.backups.is_empty() && !state.is_done(Step::Backup) {
+        backup_files(&options.backups).context(Step::Backup)?;
+        state.record(Step::Backup).context(Step::Resume)?;
+    }
+    let manifest_version = &state.manifest_version;
+    for (path, old) in update_versions(
+        manifest_version,
+        options.version_file,
+        options.edit_manifest,
+    )
+    .context(Step::ManifestUpdate)?
+    {
+        println!("{}: {} → {}", path, old, manifest_version);
+    }
+    if let Some(path) = options.version_file {
+        // Created if needed, and `git commit -a` leaves untracked files out.
+        Command::new("git")
+            .args(["add", "--", path])
+            .output_success()
+            .context(Step::ManifestUpdate)?;
+    }
+
+    let old_lock = if review_lock {
+        read_lock_packages().context(Step::CargoUpdate)?
+    } else {
+        vec![]
+    };
+
+    cargo_update(options.locked)
+        .output_success()
+        .context(Step::CargoUpdate)?;
+
+    if review_lock {
+        review_lock_changes(&old_lock).context(Step::LockfileReview)?;
+    }
+
+    // Not idempotent, unlike the rest of the bump.
+    if options.keepachangelog && !state.is_done(Step::ChangelogRelease) {
+        let new_tag = options.tag_template.name(&state.new_version);
+        release_changelog(options.changelog_file, &state.new_version, &new_tag)
+            .context(Step::ChangelogRelease)?;
+        state.record(Step::ChangelogRelease).context(Step::Resume)?;
+    }
+
+    if let Some(command) = matches.value_of("post-bump-command") {
+        shell(command)
+            .env("RSLEASE_NEW_VERSION", state.new_version.to_string())
+            .output_success()
+            .context(format!("`{}` failed", command))
+            .context(Step::PostBumpCommand)?;
+    }
+    state.record(Step::Bump).context(Step::Resume)?;
+}
+
+/// Commit the release, and the lockfile first with --commit-lock-separately.
+#[throws]
+fn commit(
+    options: &Options,
+    state: &mut ReleaseState,
+    message: &str,
+    paths: Option<&[&str]>,
+    lock: &str,
+) {
+    // Already committed if the release commit itself failed before a --resume.
+    if options.matches.is_present("commit-lock-separately")
+        && !Command::new("git")
+            .args(["status", "--porcelain", "--", lock])
+            .output_success()
+            .context(Step::LockfileCommit)?
+            .stdout
+            .is_empty()
+    {
+        options
+            .authoring
+            .commit(LOCK_MESSAGE, Some(&[lock]))
+            .context(Step::LockfileCommit)?;
+    }
+    options
+        .authoring
+        .commit(message, paths)
+        .context(Step::ReleaseCommit)?;
+    state.release_commit = Some(rev_parse("HEAD").context(Step::ReleaseCommit)?);
+    state.record(Step::ReleaseCommit).context(Step::Resume)?;
+}
+
+/// Tag the release commit, with the release notes of the changelog as the body with
+/// --tag-body-from-changelog.
+#[throws]
+fn tag(options: &Options, state: &mut ReleaseState, message: &str) {
+    let new_version = &state.new_version;
+    let new_tag = options.tag_template.name(new_version);
+    let body = if options.matches.is_present("tag-body-from-changelog") {
+        let section =
+            release_notes(options.changelog_file, new_version).context(Step::ReleaseTag)?;
+        if section.is_none() {
+            eprintln!(
+                "Warning: no section for {} in {}, the tag has no release notes.",
+                new_version, options.changelog_file
+            );
+        }
+        section
+    } else {
+        None
+    };
+    match body {
+        Some(body) => options
+            .authoring
+            .tag_body_command(&new_tag)
+            .input_success(&format!("{}\n\n{}\n", message, body))
+            .map(drop),
+        None => options.authoring.tag(&new_tag, message, false),
+    }
+    .context(Step::ReleaseTag)?;
+    state.record(Step::ReleaseTag).context(Step::Resume)?;
+}
+
+/// Check that the release, its tag or its commit, has the version it should.
+#[throws]
+fn verify_release(options: &Options, release_ref: &str, manifest_version: &Version) {
+    let committed =
+        committed_version(release_ref, options.version_file).context(Step::ReleaseVerification)?;
+    if committed.to_string() != manifest_version.to_string() {
+        bail!(anyhow!(
+            "{} has version {} in {} instead of {}.",
+            if options.no_tag {
+                "The release commit"
+            } else {
+                release_ref
+            },
+            committed,
+            options.version_file.unwrap_or("Cargo.toml"),
+            manifest_version
+        )
+        .context(Step::ReleaseVerification));
+    }
+}
+
+/// Write and commit the development version that follows the release.
+#[throws]
+fn post_release(options: &Options, state: &mut ReleaseState, paths: Option<&[&str]>) {
+    if let Some(post_version) = &state.post_version {
+        for (path, old) in
+            update_versions(post_version, options.version_file, options.edit_manifest)
+                .context(Step::PostReleaseManifestUpdate)?
+        {
+            println!("{}: {} → {}", path, old, post_version);
+        }
+
+        cargo_update(options.locked)
+            .output_success()
+            .context(Step::PostReleaseCargoUpdate)?;
+
+        options
+            .authoring
+            .commit(POST_RELEASE_MESSAGE, paths)
+            .context(Step::PostReleaseCommit)?;
+        state
+            .record(Step::PostReleaseCommit)
+            .context(Step::Resume)?;
+    }
+}
+
+/// Push the branch and the tag, and publish the docs with --publish-docs.
+#[throws]
+fn publish(options: &Options, state: &mut ReleaseState, release_ref: &str) {
+    let matches = options.matches;
+    if !state.is_done(Step::Push) {
+        push_command(matches.values_of("push-option"))?
+            .output_success()
+            .context(Step::Push)?;
+        state.record(Step::Push).context(Step::Resume)?;
+    }
+
+    if !options.no_tag {
+        let new_tag = options.tag_template.name(&state.new_version);
+        push_command(matches.values_of("push-option"))?
+            .args(["origin", &new_tag])
+            .output_success()
+            .context(Step::TagPush)?;
+    }
+
+    if matches.is_present("publish-docs") && !state.is_done(Step::DocsPublication) {
+        publish_docs(
+            release_ref,
+            &state.new_version,
+            matches.value_of("docs-branch").unwrap_or_default(),
+            &options.authoring,
+            matches.values_of("push-option"),
+            options.workspace,
+        )
+        .context(Step::DocsPublication)?;
+        state.record(Step::DocsPublication).context(Step::Resume)?;
+    }
+}
+
+/// Warn if the release is not reachable from the default branch, see
+/// --verify-reachable. The release is out already, so this only warns.
+fn warn_unreachable(release_ref: &str) {
+    let reachable = Command::new("git")
+        .arg("fetch")
+        .output_success()
+        .and_then(|_| default_branch())
+        .and_then(|default| Ok((is_ancestor(release_ref, &default)?, default)));
+    match reachable {
+        Ok((true, _)) => {}
+        Ok((false, default)) => eprintln!(
+            "Warning: {} is not reachable from {}, it may never be merged.",
+            release_ref, default
+        ),
+        Err(e) => eprintln!(
+            "Warning: could not check that {} is reachable: {:#}",
+            release_ref, e
+        ),
+    }
+}
+
+type AVoid = ARes<()>;
+
+/// The steps `release` goes through for these options, with the command each one
+/// runs, if any. Nothing is changed, so whatever depends on the repository state (the
+/// versions, whether a post-release commit is needed) appears as a `{placeholder}`.
+#[throws]
+fn plan(options: &Options) -> Vec<(Step, Option<Command>)> {
+    let matches = options.matches;
+    let (authoring, gates, tags) = (&options.authoring, &options.gates, &options.tag_template);
+    let (no_push, no_tag, locked) = (options.no_push, options.no_tag, options.locked);
+    let version_path = options.version_file.unwrap_or("Cargo.toml");
+    let git = |args: &[&str]| {
+        let mut git = Command::new("git");
+        git.args(args);
+        Some(git)
+    };
+    let mut plan = vec![];
+    if let Some(branch) = matches.value_of("commit") {
+        plan.push((Step::Checkout, git(&["checkout", branch])));
+    }
+    if matches.is_present("allow-branch") {
+        plan.push((
+            Step::BranchCheck,
+            git(&["rev-parse", "--abbrev-ref", "HEAD"]),
+        ));
+    }
+    if !options.bare {
+        plan.push((
+            Step::CleanTreeCheck,
+            Some(clean_check(
+                matches.values_of("dirty-allow"),
+                &options.backups,
+            )?),
+        ));
+    }
+    if !no_push || matches.is_present("fetch-tags") {
+        plan.push((Step::Fetch, git(&["fetch", "--tags"])));
+    }
+    plan.push((Step::Fetch, git(&["rev-parse", "--is-shallow-repository"])));
+    if !no_push && !options.bare {
+        plan.push((
+            Step::UpstreamCheck,
+            git(&["rev-list", "HEAD..HEAD@{upstream}"]),
+        ));
+    }
+    if matches.is_present("require-merged") {
+        plan.push((
+            Step::MergedCheck,
+            git(&["symbolic-ref", "--short", "refs/remotes/origin/HEAD"]),
+        ));
+        plan.push((
+            Step::MergedCheck,
+            git(&["merge-base", "--is-ancestor", "HEAD", "{default_branch}"]),
+        ));
+    }
+    let tag = tags.name("{version}");
+    let message = "Release version {version}.";
+    if matches.is_present("tag-existing") {
+        let force = matches.is_present("force-tag");
+        plan.push((
+            Step::ReleaseVerification,
+            git(&["show", &format!("HEAD:./{}", version_path)]),
+        ));
+        plan.push((
+            Step::ReleaseTag,
+            Some(authoring.tag_command(&tag, message, force)),
+        ));
+        if !no_push {
+            let mut push = push_command(matches.values_of("push-option"))?;
+            push.arg("origin");
+            if force {
+                push.arg("--force");
+            }
+            push.arg(&tag);
+            plan.push((Step::TagPush, Some(push)));
+        }
+        return plan;
+    }
+    if matches.is_present("require-changelog-entries") || options.keepachangelog {
+        plan.push((Step::ChangelogCheck, None));
+    }
+    for command in matches.values_of("pre-bump-command").into_iter().flatten() {
+        plan.push((Step::PreBumpCommand, Some(shell(command))));
+    }
+    plan.push((Step::TagDiscovery, git(&["tag", "--list"])));
+    if matches.is_present("include-remote-tags") {
+        plan.push((Step::TagDiscovery, git(&["ls-remote", "--tags", "origin"])));
+    }
+    let base_tag = tags.name("{base_version}");
+    if !matches.is_present("allow-empty-release") {
+        plan.push((
+            Step::EmptyReleaseCheck,
+            Some(commits_since_command(&base_tag)),
+        ));
+    }
+    if options.max_commits.is_some() || options.min_commits.is_some() {
+        plan.push((
+            Step::CommitCountCheck,
+            Some(commits_since_command(&base_tag)),
+        ));
+    }
+    if matches.is_present("verify-base") {
+        plan.push((Step::BaseVerification, None));
+    }
+    if matches.is_present("build-from-git") {
+        plan.push((
+            Step::VersionComputation,
+            git(&["rev-parse", "--short", "HEAD"]),
+        ));
+    }
+    plan.push((
+        Step::VersionComputation,
+        git(&["check-ref-format", &format!("refs/tags/{}", tag)]),
+    ));
+    if options.dry_run {
+        return plan;
+    }
+    if matches.is_present("tag-only-no-commit") {
+        plan.push((
+            Step::ReleaseTag,
+            Some(authoring.tag_command(&tag, message, false)),
+        ));
+        if !no_push {
+            let mut push = push_command(matches.values_of("push-option"))?;
+            push.args(["origin", &tag]);
+            plan.push((Step::TagPush, Some(push)));
+        }
+        return plan;
+    }
+    if !options.backups.is_empty() {
+        plan.push((Step::Backup, None));
+    }
+    plan.push((Step::ManifestUpdate, None));
+    if let Some(path) = options.version_file {
+        plan.push((Step::ManifestUpdate, git(&["add", "--", path])));
+    }
+    plan.push((Step::CargoUpdate, Some(cargo_update(locked))));
+    if matches.is_present("review-lock") {
+        plan.push((Step::LockfileReview, None));
+    }
+    if options.keepachangelog {
+        plan.push((Step::ChangelogRelease, None));
+    }
+    if let Some(command) = matches.value_of("post-bump-command") {
+        plan.push((Step::PostBumpCommand, Some(shell(command))));
+    }
+    let cargo = |args: &[&str]| {
+        let mut cargo = Command::new("cargo");
+        cargo.args(args);
+        Some(cargo)
+    };
+    for (step, args) in gates.commands() {
+        let args: Vec<_> = args.iter().map(String::as_str).collect();
+        plan.push((step, cargo(&args)));
+    }
+    plan.push((Step::CargoFmt, cargo(&fmt_args(options.workspace))));
+    if matches.is_present("no-commit") {
+        return plan;
+    }
+    let lock = lock_path()?;
+    let commit_paths = commit_paths(options, &lock)?;
+    let commit_paths = commit_paths.as_deref();
+    let commit_message = format!("{}{}", message, closing_lines(&options.closed_issues));
+    if matches.is_present("commit-lock-separately") {
+        plan.push((
+            Step::LockfileCommit,
+            git(&["status", "--porcelain", "--", &lock]),
+        ));
+        for commit in authoring.commit_commands(LOCK_MESSAGE, Some(&[&lock])) {
+            plan.push((Step::LockfileCommit, Some(commit)));
+        }
+    }
+    for commit in authoring.commit_commands(&commit_message, commit_paths) {
+        plan.push((Step::ReleaseCommit, Some(commit)));
+    }
+    plan.push((Step::ReleaseCommit, git(&["rev-parse", "HEAD"])));
+    if matches.is_present("fresh-verify") {
+        let dir = "{temporary_directory}";
+        plan.push((
+            Step::FreshVerification,
+            git(&["worktree", "add", "--detach", dir, "HEAD"]),
+        ));
+        for (_, args) in gates.commands() {
+            let args: Vec<_> = args.iter().map(String::as_str).collect();
+            plan.push((Step::FreshVerification, cargo(&args)));
+        }
+        plan.push((
+            Step::FreshVerification,
+            git(&["worktree", "remove", "--force", dir]),
+        ));
+    }
+    let release_ref = if no_tag { "{release_commit}" } else { &tag };
+    if matches.is_present("tag-body-from-changelog") {
+        plan.push((Step::ReleaseTag, Some(authoring.tag_body_command(&tag))));
+    } else if !no_tag {
+        plan.push((
+            Step::ReleaseTag,
+            Some(authoring.tag_command(&tag, message, false)),
+        ));
+    }
+    plan.push((
+        Step::ReleaseVerification,
+        git(&["show", &format!("{}:./{}", release_ref, version_path)]),
+    ));
+    if !no_tag {
+        plan.push((
+            Step::ReleaseTag,
+            git(&["rev-parse", &format!("{}^{{}}", tag)]),
+        ));
+    }
+    if matches.is_present("install") {
+        let args = matches.value_of("install-args").unwrap_or_default();
+        plan.push((Step::CargoInstall, Some(cargo_install(locked, args))));
+    }
+    if options.develops_after(None) {
+        plan.push((Step::PostReleaseManifestUpdate, None));
+        plan.push((Step::PostReleaseCargoUpdate, Some(cargo_update(locked))));
+        for commit in authoring.commit_commands(POST_RELEASE_MESSAGE, commit_paths) {
+            plan.push((Step::PostReleaseCommit, Some(commit)));
+        }
+    }
+    if !options.backups.is_empty() && !matches.is_present("keep-backup") {
+        plan.push((Step::BackupRemoval, None));
+    }
+    if commit_paths.is_none() && !matches.is_present("no-verify-clean-after") {
+        plan.push((
+            Step::FinalCleanTreeCheck,
+            Some(clean_check(
+                matches.values_of("dirty-allow"),
+                &options.backups,
+            )?),
+        ));
+    }
+    if !no_push {
+        let push = || push_command(matches.values_of("push-option"));
+        plan.push((Step::Push, Some(push()?)));
+        if !no_tag {
+            let mut push = push()?;
+            push.args(["origin", &tag]);
+            plan.push((Step::TagPush, Some(push)));
+        }
+        if matches.is_present("publish-docs") {
+            plan.push((Step::DocsPublication, None));
+        }
+    }
+    if matches.is_present("verify-reachable") {
+        plan.push((Step::ReachabilityCheck, git(&["fetch"])));
+        plan.push((
+            Step::ReachabilityCheck,
+            git(&["symbolic-ref", "--short", "refs/remotes/origin/HEAD"]),
+        ));
+        plan.push((
+            Step::ReachabilityCheck,
+            git(&[
+                "merge-base",
+                "--is-ancestor",
+                release_ref,
+                "{default_branch}",
+            ]),
+        ));
+    }
+    plan
+}
+
+fn print_plan(plan: &[(Step, Option<Command>)]) {
+    println!("[");
+    for (i, (step, command)) in plan.iter().enumerate() {
+        let command = match command {
+            Some(command) => {
+                let words: Vec<_> = command_words(command)
+                    .iter()
+                    .map(|word| json_string(word))
+                    .collect();
+                format!("[{}]", words.join(", "))
+            }
+            None => "null".to_owned(),
+        };
+        let separator = if i + 1 < plan.len() { "," } else { "" };
+        println!(
+            "  {{\"step\": {}, \"command\": {}}}{}",
+            json_string(step.name()),
+            command,
+            separator
+        );
+    }
+    println!("]");
+}
+
+/// One shell command line per step, the steps that rslease performs itself commented.
+fn dump_commands(plan: &[(Step, Option<Command>)]) {
+    for (step, command) in plan {
+        match command {
+            Some(command) => {
+                let words: Vec<_> = command_words(command)
+                    .iter()
+                    .map(|word| shell_quote(word))
+                    .collect();
+                println!("{}", words.join(" "));
+            }
+            None => println!("# {}", step.name()),
+        }
+    }
+}
+
+/// A word as a POSIX shell would read it back, single-quoted unless needless.
+fn shell_quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.to_owned();
+    }
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// The program and arguments of a command.
+fn command_words(command: &Command) -> Vec<String> {
+    let mut words = vec![command.get_program().to_string_lossy().into_owned()];
+    words.extend(
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned()),
+    );
+    words
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+const POST_RELEASE_MESSAGE: &str = "Post-release.";
+const LOCK_MESSAGE: &str = "Update Cargo.lock.";
+
+/// A user-provided command line, run by the platform shell.
+fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut sh = Command::new("sh");
+        sh.arg("-c");
+        sh
+    };
+    shell.arg(command);
+    shell
+}
+
+trait CommandPropagate {
+    fn output_success(&mut self) -> ARes<Output>;
+    /// As `output_success`, with `input` written to stdin.
+    fn input_success(&mut self, input: &str) -> ARes<Output>;
+    fn empty_stdout(&mut self) -> AVoid;
+}
+
+impl CommandPropagate for Command {
+    fn output_success(&mut self) -> ARes<Output> {
+        success(self.output()?)
+    }
+
+    fn input_success(&mut self, input: &str) -> ARes<Output> {
+        let mut child = self
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input.as_bytes())?;
+        }
+        success(child.wait_with_output()?)
+    }
+
+    fn empty_stdout(&mut self) -> AVoid {
+        let output = self.output_success()?;
+        if !output.stdout.is_empty() {
+            let stdout = String::from_utf8(output.stdout)?.trim().to_owned();
+            bail!(anyhow!(stdout).context("Command stdout should be empty"));
+        }
+        Ok(())
+    }
+}
+
+/// The output of a finished command, or its stderr (else stdout) as error on failure.
+fn success(output: Output) -> ARes<Output> {
+    if !output.status.success() {
+        let stderr = String::from_utf8(output.stderr)?.trim().to_owned();
+        if stderr.is_empty() {
+            let stdout = String::from_utf8(output.stdout)?.trim().to_owned();
+            let status = format!("Command failed with {}", output.status);
+            if stdout.is_empty() {
+                bail!(status);
+            }
+            bail!(anyhow!(stdout).context(status));
+        }
+        bail!(stderr);
+    }
+    Ok(output)
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum ReleaseType {
+    Major,
+    Minor,
+    Patch,
+    /// CalVer: YYYY.M.N.
+    Calendar,
+    /// The prerelease counter: x.y.z-rc.N.
+    Prerelease,
+}
+
+#[derive(Eq, PartialEq)]
+enum DevStyle {
+    /// The following version, incremented as for a release of this type.
+    Next(ReleaseType),
+    Restore,
+}
+
+/// Replace the version of Cargo.toml, returning the one it had.
+#[throws]
+fn update_cargo_toml_version(version: &Version) -> Version {
+    let manifest = read_cargo_toml()?;
+    let old = manifest_version(&manifest)?;
+    File::create("Cargo.toml")?.write_all(bump_version(&manifest, version)?.as_bytes())?;
+    old
+}
+
+/// Set the version of Cargo.toml, unless --version-file-only, and of --version-file,
+/// returning the version each file had, if any.
+#[throws]
+fn update_versions(
+    version: &Version,
+    version_file: Option<&str>,
+    edit_manifest: bool,
+) -> Vec<(String, Version)> {
+    let mut replaced = vec![];
+    if edit_manifest {
+        replaced.push(("Cargo.toml".to_owned(), update_cargo_toml_version(version)?));
+    }
+    if let Some(path) = version_file {
+        if let Some(old) = update_version_file(path, version)? {
+            replaced.push((path.to_owned(), old));
+        }
+    }
+    replaced
+}
+
+/// The content of --version-file, or None if it does not exist yet.
+#[throws]
+fn version_file_content(path: &str) -> Option<String> {
+    let mut content = String::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_string(&mut content),
+        Err(e) if e.kind() == ErrorKind::NotFound => return None,
+        Err(e) => Err(e),
+    }
+    .context(format!("Failed to read {}", path))?;
+    Some(content)
+}
+
+/// The version of --version-file, or None if it does not exist yet.
+#[throws]
+fn read_version_file(path: &str) -> Option<Version> {
+    match version_file_content(path)? {
+        Some(content) => {
+            Some(Version::parse(content.trim()).context(format!("{}: invalid version", path))?)
+        }
+        None => None,
+    }
+}
+
+/// Write `version` to --version-file, with a trailing newline unless the file had none,
+/// returning the version it had, if it existed.
+#[throws]
+fn update_version_file(path: &str, version: &Version) -> Option<Version> {
+    let old = read_version_file(path)?;
+    let newline = match version_file_content(path)? {
+        Some(content) if !content.ends_with('\n') => "",
+        Some(_) => "\n",
+        None => {
+            if let Some(parent) = Path::new(path).parent() {
+                create_dir_all(parent)?;
+            }
+            "\n"
+        }
+    };
+    File::create(path)?.write_all(format!("{}{}", version, newline).as_bytes())?;
+    old
+}
+
+/// The version of --version-file if it exists, otherwise of Cargo.toml.
+#[throws]
+fn current_version(version_file: Option<&str>) -> Version {
+    match version_file.map(read_version_file).transpose()?.flatten() {
+        Some(version) => version,
+        None => manifest_version(&read_cargo_toml()?)?,
+    }
+}
+
+/// The current version, which must be a `-dev` prerelease, without its prerelease.
+#[throws]
+fn dev_base(version_file: Option<&str>) -> Version {
+    let mut version = current_version(version_file)?;
+    if version.pre != [dev_identifier()] {
+        bail!(
+            "--strip-dev-on-base: version {} is not a -dev prerelease.",
+            version
+        );
+    }
+    version.pre.clear();
+    version
+}
+
+/// The next prerelease of `latest`: its last numeric identifier incremented, or `.1`
+/// appended if there is none (rc → rc.1).
+#[throws]
+fn prerelease_version(latest: &Version) -> Version {
+    if !latest.is_prerelease() {
+        bail!(
+            "--prerelease: latest version {} is not a prerelease, nothing to bump.",
+            latest
+        );
+    }
+    let mut version = latest.clone();
+    match version.pre.iter_mut().rev().find_map(|i| match i {
+        Identifier::Numeric(n) => Some(n),
+        Identifier::AlphaNumeric(_) => None,
+    }) {
+        Some(n) => *n += 1,
+        None => version.pre.push(Identifier::Numeric(1)),
+    }
+    version
+}
+
+/// A numeric field of the version, for --increment.
+#[derive(Clone, Copy)]
+enum Part {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl Part {
+    fn of(self, version: &mut Version) -> &mut u64 {
+        match self {
+            Part::Major => &mut version.major,
+            Part::Minor => &mut version.minor,
+            Part::Patch => &mut version.patch,
+        }
+    }
+}
+
+/// An operation of --increment.
+enum Increment {
+    /// `<part>+<n>`.
+    Add(Part, u64),
+    /// `<part>=<n>`.
+    Set(Part, u64),
+    /// `pre=<identifiers>`, empty to remove the prerelease.
+    Pre(Vec<Identifier>),
+}
+
+/// Parse --increment, see --help.
+#[throws]
+fn parse_increment(expression: &str) -> Vec<Increment> {
+    let operation = Regex::new(r"^\s*([a-z]+)\s*([+=])\s*(\S*)\s*$")?;
+    let mut increment = vec![];
+    for text in expression.split(',') {
+        let c = operation.captures(text).ok_or_else(|| {
+            anyhow!(
+                "--increment: expected `<field>+<n>` or `<field>=<value>`, got `{}`.",
+                text.trim()
+            )
+        })?;
+        let part = match &c[1] {
+            "major" => Part::Major,
+            "minor" => Part::Minor,
+            "patch" => Part::Patch,
+            "pre" if &c[2] == "=" => {
+                let pre = if c[3].is_empty() {
+                    vec![]
+                } else {
+                    let version = Version::parse(&format!("0.0.0-{}", &c[3]))
+                        .context(format!("--increment: invalid prerelease `{}`", &c[3]))?;
+                    // The parser takes empty identifiers, and a build after the prerelease.
+                    let empty = version.pre.iter().any(|i| i.to_string().is_empty());
+                    if empty || !version.build.is_empty() {
+                        bail!("--increment: invalid prerelease `{}`", &c[3]);
+                    }
+                    version.pre
+                };
+                increment.push(Increment::Pre(pre));
+                continue;
+            }
+            "pre" => bail!("--increment: pre can only be set, with `pre=`."),
+            "build" => bail!("--increment: tag names cannot carry build metadata."),
+            field => bail!(
+                "--increment: unknown field `{}`, expected major, minor, patch or pre.",
+                field
+            ),
+        };
+        let n = c[3]
+            .parse::<u64>()
+            .context(format!("--increment: `{}`: not a number", text.trim()))?;
+        increment.push(if &c[2] == "+" {
+            Increment::Add(part, n)
+        } else {
+            Increment::Set(part, n)
+        });
+    }
+    increment
+}
+
+/// Apply --increment to `from`, which the result must be above.
+#[throws]
+fn apply_increment(from: &Version, increment: &[Increment]) -> Version {
+    let mut version = from.clone();
+    for operation in increment {
+        match operation {
+            Increment::Add(part, n) => {
+                let value = part.of(&mut version);
+                *value = value
+                    .checked_add(*n)
+                    .ok_or_else(|| anyhow!("--increment: overflow from {}", from))?;
+            }
+            Increment::Set(part, n) => *part.of(&mut version) = *n,
+            Increment::Pre(pre) => version.pre = pre.clone(),
+        }
+    }
+    if version <= *from {
+        bail!(
+            "--increment: {} is not above the base version {}.",
+            version,
+            from
+        );
+    }
+    version
+}
+
+/// The CalVer release following `latest`: the next one of the month, or the first.
+#[throws]
+fn calendar_version(latest: &Version) -> Version {
+    let (year, month, _) = current_date()?;
+    if latest.major == year && latest.minor == month {
+        let mut version = latest.clone();
+        version.increment_patch();
+        version
+    } else if (latest.major, latest.minor) > (year, month) {
+        bail!(
+            "Latest version {} is in the future, check the clock.",
+            latest
+        );
+    } else {
+        Version::new(year, month, 0)
+    }
+}
+
+/// The current UTC year, month and day, from the days since the epoch.
+#[throws]
+fn current_date() -> (u64, u64, u64) {
+    let days = (SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() / 86_400) as i64;
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as u64, month as u64, day as u64)
+}
+
+/// The development version following a release, see --dev-style.
+fn dev_version(released: &Version, style: &DevStyle) -> Version {
+    let mut version = released.clone();
+    match style {
+        Next(Major) => version.increment_major(),
+        Next(Patch) => version.increment_patch(),
+        Next(_) => version.increment_minor(),
+        Restore => {}
+    }
+    version.pre = vec![dev_identifier()];
+    version
+}
+
+fn dev_identifier() -> Identifier {
+    Identifier::AlphaNumeric("dev".to_owned())
+}
+
+/// The version in the Cargo.toml of a commit, or in its --version-file.
+#[throws]
+fn committed_version(rev: &str, version_file: Option<&str>) -> Version {
+    match version_file {
+        Some(path) => Version::parse(committed_file(rev, path)?.trim())
+            .context(format!("{}: invalid version", path))?,
+        None => manifest_version(&committed_manifest(rev)?)?,
+    }
+}
+
+/// The Cargo.toml of a commit.
+#[throws]
+fn committed_manifest(rev: &str) -> String {
+    committed_file(rev, "Cargo.toml")?
+}
+
+/// A file of a commit, relative to the current directory.
+#[throws]
+fn committed_file(rev: &str, path: &str) -> String {
+    // `./` is relative to the working tree, a bare repository can only mean the root.
+    let prefix = if is_bare_repository()? { "" } else { "./" };
+    let out = Command::new("git")
+        .args(["show", &format!("{}:{}{}", rev, prefix, path)])
+        .output_success()?;
+    String::from_utf8(out.stdout)?
+}
+
+#[throws]
+fn read_cargo_toml() -> String {
+    let mut manifest = String::new();
+    File::open("Cargo.toml")?.read_to_string(&mut manifest)?;
+    manifest
+}
+
+/// The first `version = ".."` line of a manifest, see --help.
+#[throws]
+fn version_regex() -> Regex {
+    Regex::new(r#"(?m)^(version\s*=\s*")([^"]*)("\s*)$"#)?
+}
+
+#[throws]
+fn manifest_version(manifest: &str) -> Version {
+    match version_regex()?.captures(manifest) {
+        Some(c) => Version::parse(&c[2]).context("Invalid version in Cargo.toml")?,
+        None => bail!("Could not extract version from Cargo.toml, see --help for more info."),
+    }
+}
+
+/// The `name` of the [package] table of a manifest.
+#[throws]
+fn manifest_name(manifest: &str) -> String {
+    let table = Regex::new(r"(?ms)^\[package\]\s*$(.*?)(?:^\[|\z)")?;
+    let name = Regex::new(r#"(?m)^name\s*=\s*"([^"]*)"\s*$"#)?;
+    let package = table.captures(manifest).and_then(|c| c.get(1));
+    match package.and_then(|p| name.captures(p.as_str())) {
+        Some(c) => c[1].to_owned(),
+        None => bail!("Could not extract the package name from Cargo.toml."),
+    }
+}
+
+/// Check that the version a workspace manifest has first is the shared one, see --help.
+#[throws]
+fn check_workspace_version(manifest: &str) {
+    let table = Regex::new(r"(?ms)^\[workspace\.package\]\s*$(.*?)(?:^\[|\z)")?;
+    let first = version_regex()?.find(manifest).map(|m| m.start());
+    let shared = table.captures(manifest).and_then(|c| c.get(1));
+    match (shared, first) {
+        (Some(shared), Some(first)) if shared.range().contains(&first) => {}
+        _ => bail!(
+            "--workspace: the first `version = ..` of Cargo.toml must belong to \
+             [workspace.package]."
+        ),
+    }
+}
+
+/// Replace the version of a manifest.
+#[throws]
+fn bump_version(manifest: &str, version: &Version) -> String {
+    let re = version_regex()?;
+    if !re.is_match(manifest) {
+        bail!("Could not extract version from Cargo.toml, see --help for more info.");
+    }
+    re.replace(manifest, |c: &Captures| {
+        format!("{}{}{}", &c[1], version, &c[3])
+    })
+    .into_owned()
+}
+
+/// A unified diff of two texts, with the usual three lines of context.
+fn unified_diff(name: &str, old: &str, new: &str) -> String {
+    const CONTEXT: usize = 3;
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    // Edit script as (op, position in old, position in new).
+    let mut ops = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push((' ', i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', i, j));
+            i += 1;
+        } else {
+            ops.push(('+', i, j));
+            j += 1;
+        }
+    }
+    let changes: Vec<_> = (0..ops.len()).filter(|&k| ops[k].0 != ' ').collect();
+    let mut diff = String::new();
+    if changes.is_empty() {
+        return diff;
+    }
+    diff.push_str(&format!("--- a/{0}\n+++ b/{0}\n", name));
+    let mut k = 0;
+    while k < changes.len() {
+        let start = changes[k].saturating_sub(CONTEXT);
+        let mut last = changes[k];
+        while k < changes.len() && changes[k] <= last + 2 * CONTEXT {
+            last = changes[k];
+            k += 1;
+        }
+        let hunk = &ops[start..(last + CONTEXT + 1).min(ops.len())];
+        let old_len = hunk.iter().filter(|op| op.0 != '+').count();
+        let new_len = hunk.iter().filter(|op| op.0 != '-').count();
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk[0].1 + 1,
+            old_len,
+            hunk[0].2 + 1,
+            new_len
+        ));
+        for &(op, i, j) in hunk {
+            let line = if op == '+' { new[j] } else { old[i] };
+            diff.push_str(&format!("{}{}\n", op, line));
+        }
+    }
+    diff
+}
+
+/// How the commits and the tag rslease creates are made.
+struct Authoring {
+    /// `-c` overrides for the git commands creating objects.
+    config: Vec<String>,
+    /// `Name <email>`, see --author.
+    author: Option<String>,
+    sign_tag: bool,
+    sign_commits: bool,
+    /// `Signed-off-by` trailers, see --signoff.
+    signoff: bool,
+}
+
+impl Authoring {
+    fn git(&self) -> Command {
+        let mut git = Command::new("git");
+        for config in &self.config {
+            git.arg("-c").arg(config);
+        }
+        git
+    }
+
+    /// Commit either every tracked modification, or exactly `paths`.
+    #[throws]
+    fn commit(&self, message: &str, paths: Option<&[&str]>) {
+        for mut command in self.commit_commands(message, paths) {
+            command.output_success()?;
+        }
+    }
+
+    fn commit_commands(&self, message: &str, paths: Option<&[&str]>) -> Vec<Command> {
+        let mut commands = vec![];
+        let mut commit = self.git();
+        commit.arg("commit");
+        if self.sign_commits {
+            commit.arg("-S");
+        }
+        if self.signoff {
+            commit.arg("-s");
+        }
+        if let Some(author) = &self.author {
+            commit.arg(format!("--author={}", author));
+        }
+        match paths {
+            Some(paths) => {
+                let mut add = Command::new("git");
+                add.arg("add").args(paths);
+                commands.push(add);
+                commit.args(["-m", message, "--"]).args(paths);
+            }
+            None => {
+                commit.args(["-am", message]);
+            }
+        }
+        commands.push(commit);
+        commands
+    }
+
+    #[throws]
+    fn tag(&self, name: &str, message: &str, force: bool) {
+        self.tag_command(name, message, force).output_success()?;
+    }
+
+    fn tag_command(&self, name: &str, message: &str, force: bool) -> Command {
+        let mut tag = self.git();
+        tag.arg("tag");
+        if force {
+            tag.arg("--force");
+        }
+        if self.sign_tag {
+            tag.args(["-s", "-m", message]);
+        }
+        tag.arg(name);
+        tag
+    }
+
+    /// An annotated tag whose message is read from stdin, keeping its `#` headings.
+    fn tag_body_command(&self, name: &str) -> Command {
+        let mut tag = self.git();
+        tag.arg("tag");
+        tag.arg(if self.sign_tag { "-s" } else { "-a" });
+        tag.args(["--cleanup=whitespace", "-F", "-", name]);
+        tag
+    }
+}
+
+/// `git config --get`, for the key ending `args`, or None if it is not set.
+#[throws]
+fn git_config(args: &[&str]) -> Option<String> {
+    let out = Command::new("git")
+        .args(["config", "--get"])
+        .args(args)
+        .output()?;
+    // Exit code 1 means that the key is not set.
+    match out.status.code() {
+        Some(0) => Some(String::from_utf8(out.stdout)?.trim().to_owned()),
+        Some(1) => None,
+        _ => bail!(
+            "`git config {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ),
+    }
+}
+
+/// The number of entries under the `## [Unreleased]` heading of a Keep a Changelog
+/// file, or None without such a heading. Subsection headings are not entries.
+#[throws]
+fn unreleased_entries(changelog: &str) -> Option<usize> {
+    let heading = Regex::new(r"(?i)^##\s*\[?unreleased\]?\s*$")?;
+    let link = link_regex()?;
+    let mut lines = changelog
+        .lines()
+        .skip_while(|line| !heading.is_match(line.trim()));
+    if lines.next().is_none() {
+        return None;
+    }
+    let entries = lines
+        .take_while(|line| !line.starts_with("## ") && !link.is_match(line))
+        .filter(|line| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .count();
+    Some(entries)
+}
+
+/// A link reference definition, `[Unreleased]: https://..`, which ends the last section
+/// of a changelog.
+#[throws]
+fn link_regex() -> Regex {
+    Regex::new(r"^\[[^\]]+\]:\s")?
+}
+
+/// Turn the `## [Unreleased]` section of a Keep a Changelog file into the one of
+/// `version`, released as `tag` today, under a new empty `## [Unreleased]`.
+#[throws]
+fn release_changelog(path: &str, version: &Version, tag: &str) {
+    let mut changelog = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut changelog))
+        .context(format!("Failed to read {}", path))?;
+    let (year, month, day) = current_date()?;
+    let date = format!("{}-{:02}-{:02}", year, month, day);
+    let changelog = match released_changelog(&changelog, version, tag, &date)? {
+        Some(changelog) => changelog,
+        None => bail!("{} has no [Unreleased] section.", path),
+    };
+    File::create(path)?.write_all(changelog.as_bytes())?;
+}
+
+/// The changelog with its `## [Unreleased]` section released on `date`, or None without
+/// such a section.
+#[throws]
+fn released_changelog(changelog: &str, version: &Version, tag: &str, date: &str) -> Option<String> {
+    let heading = Regex::new(r"(?im)^##\s*\[?unreleased\]?\s*$")?;
+    if !heading.is_match(changelog) {
+        return None;
+    }
+    let changelog = heading.replace(changelog, |_: &Captures| {
+        format!("## [Unreleased]\n\n## [{}] - {}", version, date)
+    });
+    // The compare link of [Unreleased] knows the previous tag, the release goes in between.
+    let link = Regex::new(r"(?im)^\[unreleased\]:\s*(\S+)/compare/(\S+?)\.\.\.(\S+)[ \t]*$")?;
+    let changelog = link.replace(&changelog, |c: &Captures| {
+        format!(
+            "[Unreleased]: {0}/compare/{3}...{4}\n[{1}]: {0}/compare/{2}...{3}",
+            &c[1], version, &c[2], tag, &c[3]
+        )
+    });
+    Some(changelog.into_owned())
+}
+
+/// The --close-issue numbers.
+#[throws]
+fn issue_numbers(issues: Option<Values>) -> Vec<u64> {
+    let mut numbers = vec![];
+    for issue in issues.into_iter().flatten() {
+        match issue.trim_start_matches('#').parse() {
+            Ok(n) if n > 0 => numbers.push(n),
+            _ => bail!("--close-issue: {} is not an issue number.", issue),
+        }
+    }
+    numbers
+}
+
+/// The trailer closing `issues` on the forge, starting with the blank line separating it
+/// from the rest of the commit message, if there are any.
+fn closing_lines(issues: &[u64]) -> String {
+    let mut lines = String::new();
+    if !issues.is_empty() {
+        lines.push('\n');
+    }
+    for issue in issues {
+        lines.push_str(&format!("\nCloses #{}", issue));
+    }
+    lines
+}
+
+/// The entries of the `## [version]` section of a changelog, or None if there are none.
+#[throws]
+fn release_notes(path: &str, version: &Version) -> Option<String> {
+    let mut changelog = String::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_string(&mut changelog),
+        Err(e) if e.kind() == ErrorKind::NotFound => return None,
+        Err(e) => Err(e),
+    }
+    .context(format!("Failed to read {}", path))?;
+    let heading = Regex::new(&format!(
+        r"^##\s*\[?{}\]?(\s.*)?$",
+        regex::escape(&version.to_string())
+    ))?;
+    let link = link_regex()?;
+    let mut lines = changelog
+        .lines()
+        .skip_while(|line| !heading.is_match(line.trim()));
+    if lines.next().is_none() {
+        return None;
+    }
+    let section: Vec<_> = lines
+        .take_while(|line| !line.starts_with("## ") && !link.is_match(line))
+        .collect();
+    let section = section.join("\n").trim().to_owned();
+    if section.is_empty() {
+        return None;
+    }
+    Some(section)
+}
+
+/// `git push`, passing each --push-option to the server.
+#[throws]
+fn push_command(options: Option<Values>) -> Command {
+    let mut push = Command::new("git");
+    push.arg("push");
+    for option in options.into_iter().flatten() {
+        if option.trim().is_empty() {
+            bail!("--push-option: must not be empty.");
+        }
+        push.arg("-o").arg(option);
+    }
+    push
+}
+
+/// `git status`, on everything but the --dirty-allow pathspecs and the --backup copies.
+#[throws]
+fn clean_check(dirty_allow: Option<Values>, backups: &[(String, String)]) -> Command {
+    let mut status = Command::new("git");
+    status.args(["status", "--porcelain=v2"]);
+    if dirty_allow.is_some() || !backups.is_empty() {
+        // Let git apply the pathspecs: the whole tree, minus the allowed paths.
+        status.args(["--", ":/"]);
+        for pathspec in dirty_allow.into_iter().flatten() {
+            if pathspec.trim().is_empty() {
+                bail!("--dirty-allow: must not be empty.");
+            }
+            status.arg(format!(":(exclude){}", pathspec));
+        }
+        for (_, backup) in backups {
+            status.arg(format!(":(exclude,literal){}", backup));
+        }
+    }
+    status
+}
+
+/// The files the release edits, each with its --backup copy `<path>.bak`.
+#[throws]
+fn backup_paths(matches: &ArgMatches) -> Vec<(String, String)> {
+    if !matches.is_present("backup") {
+        return vec![];
+    }
+    let lock = lock_path()?;
+    let mut paths = vec![];
+    if !matches.is_present("version-file-only") {
+        paths.push("Cargo.toml");
+    }
+    paths.push(&lock);
+    paths.extend(matches.value_of("version-file"));
+    if matches.is_present("keepachangelog") {
+        paths.extend(matches.value_of("changelog-file"));
+    }
+    paths
+        .iter()
+        .map(|path| (path.to_string(), format!("{}.bak", path)))
+        .collect()
+}
+
+/// Copy each existing file to its --backup path.
+#[throws]
+fn backup_files(backups: &[(String, String)]) {
+    for (path, backup) in backups {
+        if !Path::new(path).exists() {
+            continue;
+        }
+        copy(path, backup).context(format!("Failed to copy {} to {}", path, backup))?;
+        let ignored = Command::new("git")
+            .args(["check-ignore", "--quiet", backup])
+            .status()?
+            .success();
+        if !ignored {
+            eprintln!("Warning: {} is not ignored by git.", backup);
+        }
+    }
+}
+
+/// Remove the --backup copies, those that exist.
+#[throws]
+fn remove_backups(backups: &[(String, String)]) {
+    for (_, backup) in backups {
+        match remove_file(backup) {
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            result => result.context(format!("Failed to remove {}", backup))?,
+        }
+    }
+}
+
+/// The remote default branch, e.g. `origin/main`, as of the last fetch.
+#[throws]
+fn default_branch() -> String {
+    let out = Command::new("git")
+        .args(["symbolic-ref", "--short", "refs/remotes/origin/HEAD"])
+        .output_success()
+        .context("Unknown default branch, try `git remote set-head origin --auto`")?;
+    String::from_utf8(out.stdout)?.trim().to_owned()
+}
+
+#[throws]
+fn is_ancestor(commit: &str, of: &str) -> bool {
+    let status = Command::new("git")
+        .args(["merge-base", "--is-ancestor", commit, of])
+        .status()?;
+    match status.code() {
+        Some(0) => true,
+        Some(1) => false,
+        _ => bail!("`git merge-base` failed with {}", status),
+    }
+}
+
+/// The checked out branch, `HEAD` if detached.
+#[throws]
+fn current_branch() -> String {
+    let out = Command::new("git")
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .output_success()?;
+    String::from_utf8(out.stdout)?.trim().to_owned()
+}
+
+/// A regex matching the whole of a path for a glob with `*`, `**` and `?`.
+#[throws]
+fn glob_regex(glob: &str) -> Regex {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re)?
+}
+
+#[throws]
+fn rev_parse(rev: &str) -> String {
+    let out = Command::new("git")
+        .args(["rev-parse", rev])
+        .output_success()?;
+    String::from_utf8(out.stdout)?.trim().to_owned()
+}
+
+/// The number of commits since `tag`, not counting our own post-release commit.
+#[throws]
+fn commits_since(tag: &str) -> usize {
+    let out = commits_since_command(tag).output_success()?;
+    String::from_utf8(out.stdout)?.trim().parse()?
+}
+
+fn commits_since_command(tag: &str) -> Command {
+    let mut count = Command::new("git");
+    count
+        .args(["rev-list", "--count", "--invert-grep", "--fixed-strings"])
+        .arg(format!("--grep={}", POST_RELEASE_MESSAGE))
+        .arg(format!("{}..HEAD", tag));
+    count
+}
+
+#[throws]
+fn tag_exists(name: &str) -> bool {
+    Command::new("git")
+        .args(["rev-parse", "--quiet", "--verify"])
+        .arg(format!("refs/tags/{}", name))
+        .output()?
+        .status
+        .success()
+}
+
+#[throws]
+fn is_bare_repository() -> bool {
+    // Outside of a repository, the later git commands tell better what is wrong.
+    Command::new("git")
+        .args(["rev-parse", "--is-bare-repository"])
+        .output()?
+        .stdout
+        .starts_with(b"true")
+}
+
+#[throws]
+fn is_tracked(path: &str) -> bool {
+    Command::new("git")
+        .args(["ls-files", "--error-unmatch", path])
+        .output()?
+        .status
+        .success()
+}
+
+/// Exit code of --check-releasable when there is nothing to release.
+const NOTHING_TO_RELEASE: i32 = 10;
+
+/// Why --check-releasable exits with NOTHING_TO_RELEASE, as the context of an error.
+#[derive(Debug)]
+struct NothingToRelease;
+
+impl Display for NothingToRelease {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("Nothing to release")
+    }
+}
+
+/// The error, marked as NothingToRelease with --check-releasable.
+fn nothing_to_release(check_releasable: bool, error: Error) -> Error {
+    if check_releasable {
+        error.context(NothingToRelease)
+    } else {
+        error
+    }
+}
+
+/// `cargo fmt`, of every member with --workspace.
+fn fmt_args(workspace: bool) -> Vec<&'static str> {
+    if workspace {
+        vec!["fmt", "--all"]
+    } else {
+        vec!["fmt"]
+    }
+}
+
+/// Update Cargo.lock. When locked, dependencies stay as they are and only the entries
+/// of the workspace itself, whose version was just changed, are refreshed.
+fn cargo_update(locked: bool) -> Command {
+    let mut update = Command::new("cargo");
+    update.arg("update");
+    if locked {
+        update.arg("--workspace");
+    }
+    update
+}
+
+/// `cargo install` of the crate, followed by the whitespace-separated --install-args.
+fn cargo_install(locked: bool, args: &str) -> Command {
+    let mut install = Command::new("cargo");
+    install.args(["install", "--path", "."]);
+    if locked {
+        install.arg("--locked");
+    }
+    install.args(args.split_whitespace());
+    install
+}
+
+/// The `(name, version)` of every package in Cargo.lock, if there is one.
+#[throws]
+fn read_lock_packages() -> Vec<(String, String)> {
+    let mut lock = String::new();
+    match File::open(lock_path()?) {
+        Ok(mut file) => file.read_to_string(&mut lock)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => return vec![],
+        Err(e) => Err(e)?,
+    };
+    let re = Regex::new(r#"(?m)^name = "([^"]*)"\s*\nversion = "([^"]*)"\s*$"#)?;
+    re.captures_iter(&lock)
+        .map(|c| (c[1].to_owned(), c[2].to_owned()))
+        .collect()
+}
+
+/// The path of Cargo.lock from the current directory: the nearest one up to the root of
+/// the repository, as a workspace member uses the one of its workspace.
+#[throws]
+fn lock_path() -> String {
+    let out = Command::new("git")
+        .args(["rev-parse", "--show-cdup"])
+        .output_success()?;
+    let cdup = String::from_utf8(out.stdout)?.trim().to_owned();
+    let mut up = String::new();
+    while !Path::new(&format!("{}Cargo.lock", up)).exists() {
+        if up.len() >= cdup.len() {
+            return "Cargo.lock".to_owned();
+        }
+        up.push_str("../");
+    }
+    format!("{}Cargo.lock", up)
+}
+
+/// Print how Cargo.lock changed since `old`, and have an interactive user confirm it.
+#[throws]
+fn review_lock_changes(old: &[(String, String)]) {
+    let new = read_lock_packages()?;
+    let removed: Vec<_> = old.iter().filter(|p| !new.contains(p)).collect();
+    let added: Vec<_> = new.iter().filter(|p| !old.contains(p)).collect();
+    if removed.is_empty() && added.is_empty() {
+        println!("Cargo.lock: no dependency changes.");
+        return;
+    }
+    println!("Cargo.lock changes:");
+    let mut names: Vec<_> = removed.iter().chain(&added).map(|p| &p.0).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let from: Vec<_> = removed.iter().filter(|p| &p.0 == name).collect();
+        let to: Vec<_> = added.iter().filter(|p| &p.0 == name).collect();
+        if let ([from], [to]) = (&from[..], &to[..]) {
+            println!("  {} {} -> {}", name, from.1, to.1);
+        } else {
+            for p in from {
+                println!("  - {} {}", name, p.1);
+            }
+            for p in to {
+                println!("  + {} {}", name, p.1);
+            }
+        }
+    }
+    if stdin().is_terminal() {
+        print!("Release with these changes? [y/N] ");
+        stdout().flush()?;
+        let mut answer = String::new();
+        stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            bail!("Aborted; Cargo.toml and Cargo.lock are left modified.");
+        }
+    }
+}
+
+/// Run a cargo subcommand from `dir`, explaining how to install it when it is missing
+/// from the toolchain, or skipping it with a warning if so requested (then None).
+#[throws]
+fn cargo_tool<S: AsRef<str>>(args: &[S], dir: &Path, skip_missing: bool) -> Option<Output> {
+    let result = Command::new("cargo")
+        .args(args.iter().map(AsRef::as_ref))
+        .current_dir(dir)
+        .output_success();
+    let error = match result {
+        Ok(output) => return Some(output),
+        Err(error) => error,
+    };
+    let missing = Regex::new(r"no such (sub)?command|is not installed for the toolchain")?;
+    if !missing.is_match(&error.to_string()) {
+        bail!(error);
+    }
+    if !skip_missing {
+        bail!(error.context(
+            "Missing toolchain component, try `rustup component add clippy rustfmt` \
+             or --skip-missing-components"
+        ));
+    }
+    eprintln!(
+        "Warning: skipping `cargo {}`, not installed.",
+        args[0].as_ref()
+    );
+    None
+}
+
+/// A step of the release, named in its errors, in the --plan and in the resume state.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Step {
+    CrateNameCheck,
+    TagSigning,
+    TagVerification,
+    TagPruning,
+    Checkout,
+    BranchCheck,
+    CleanTreeCheck,
+    Fetch,
+    UpstreamCheck,
+    MergedCheck,
+    ReleaseVerification,
+    ReleaseTag,
+    TagPush,
+    ChangelogCheck,
+    PreBumpCommand,
+    TagDiscovery,
+    EmptyReleaseCheck,
+    CommitCountCheck,
+    BaseVerification,
+    VersionComputation,
+    Backup,
+    ManifestUpdate,
+    CargoUpdate,
+    LockfileReview,
+    ChangelogRelease,
+    PostBumpCommand,
+    Bump,
+    CargoClippyGate,
+    CargoBuildGate,
+    FeatureMatrixGate,
+    ParallelGates,
+    CargoFmt,
+    LockfileCommit,
+    ReleaseCommit,
+    FreshVerification,
+    CargoInstall,
+    PostReleaseManifestUpdate,
+    PostReleaseCargoUpdate,
+    PostReleaseCommit,
+    BackupRemoval,
+    FinalCleanTreeCheck,
+    Push,
+    DocsPublication,
+    ReachabilityCheck,
+    Resume,
+}
+
+impl Step {
+    /// The name of the step, e.g. `release tag`.
+    fn name(self) -> &'static str {
+        match self {
+            Step::CrateNameCheck => "crate name check",
+            Step::TagSigning => "tag signing",
+            Step::TagVerification => "tag verification",
+            Step::TagPruning => "tag pruning",
+            Step::Checkout => "checkout",
+            Step::BranchCheck => "branch check",
+            Step::CleanTreeCheck => "clean tree check",
+            Step::Fetch => "fetch",
+            Step::UpstreamCheck => "upstream check",
+            Step::MergedCheck => "merged check",
+            Step::ReleaseVerification => "release verification",
+            Step::ReleaseTag => "release tag",
+            Step::TagPush => "tag push",
+            Step::ChangelogCheck => "changelog check",
+            Step::PreBumpCommand => "pre-bump command",
+            Step::TagDiscovery => "tag discovery",
+            Step::EmptyReleaseCheck => "empty release check",
+            Step::CommitCountCheck => "commit count check",
+            Step::BaseVerification => "base verification",
+            Step::VersionComputation => "version computation",
+            Step::Backup => "backup",
+            Step::ManifestUpdate => "manifest update",
+            Step::CargoUpdate => "cargo update",
+            Step::LockfileReview => "lockfile review",
+            Step::ChangelogRelease => "changelog release",
+            Step::PostBumpCommand => "post-bump command",
+            Step::Bump => "bump",
+            Step::CargoClippyGate => "cargo clippy gate",
+            Step::CargoBuildGate => "cargo build gate",
+            Step::FeatureMatrixGate => "feature matrix gate",
+            Step::ParallelGates => "parallel gates",
+            Step::CargoFmt => "cargo fmt",
+            Step::LockfileCommit => "lockfile commit",
+            Step::ReleaseCommit => "release commit",
+            Step::FreshVerification => "fresh verification",
+            Step::CargoInstall => "cargo install",
+            Step::PostReleaseManifestUpdate => "post-release manifest update",
+            Step::PostReleaseCargoUpdate => "post-release cargo update",
+            Step::PostReleaseCommit => "post-release commit",
+            Step::BackupRemoval => "backup removal",
+            Step::FinalCleanTreeCheck => "final clean tree check",
+            Step::Push => "push",
+            Step::DocsPublication => "docs publication",
+            Step::ReachabilityCheck => "reachability check",
+            Step::Resume => "resume",
+        }
+    }
+}
+
+/// The error context of the step, e.g. `step: release tag`.
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "step: {}", self.name())
+    }
+}
+
+/// How far a release went, saved in the git directory after each step that changes
+/// the repository so that --resume can go on from there.
+struct ReleaseState {
+    path: PathBuf,
+    new_version: Version,
+    manifest_version: Version,
+    post_version: Option<Version>,
+    /// The version of the base tag.
+    base_version: Version,
+    release_commit: Option<String>,
+    done: Vec<String>,
+}
+
+impl ReleaseState {
+    #[throws]
+    fn path() -> PathBuf {
+        let out = Command::new("git")
+            .args(["rev-parse", "--git-path", "rslease-state"])
+            .output_success()?;
+        PathBuf::from(String::from_utf8(out.stdout)?.trim())
+    }
+
+    /// The saved state, as `key value` lines.
+    #[throws]
+    fn load(path: &Path) -> Option<Self> {
+        let mut text = String::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut text)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return None,
+            Err(e) => Err(e)?,
+        };
+        let mut values = vec![];
+        let mut done = vec![];
+        for line in text.lines() {
+            match line.split_once(' ') {
+                Some(("done", step)) => done.push(step.to_owned()),
+                Some((key, value)) => values.push((key, value)),
+                None => bail!("Invalid line in {}: {}", path.display(), line),
+            }
+        }
+        let value = |key| values.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+        let version = |key| value(key).map(Version::parse).transpose();
+        let (new_version, manifest_version, base_version) =
+            match (version("version")?, version("manifest")?, version("base")?) {
+                (Some(new), Some(manifest), Some(base)) => (new, manifest, base),
+                _ => bail!("Missing versions in {}.", path.display()),
+            };
+        Some(ReleaseState {
+            path: path.to_owned(),
+            new_version,
+            manifest_version,
+            post_version: version("post")?,
+            base_version,
+            release_commit: value("commit").map(str::to_owned),
+            done,
+        })
+    }
+
+    #[throws]
+    fn save(&self) {
+        let mut text = format!(
+            "version {}\nmanifest {}\nbase {}\n",
+            self.new_version, self.manifest_version, self.base_version
+        );
+        if let Some(post_version) = &self.post_version {
+            text.push_str(&format!("post {}\n", post_version));
+        }
+        if let Some(commit) = &self.release_commit {
+            text.push_str(&format!("commit {}\n", commit));
+        }
+        for step in &self.done {
+            text.push_str(&format!("done {}\n", step));
+        }
+        File::create(&self.path)?.write_all(text.as_bytes())?;
+    }
+
+    fn is_done(&self, step: Step) -> bool {
+        self.done.iter().any(|done| done == step.name())
+    }
+
+    #[throws]
+    fn record(&mut self, step: Step) {
+        self.done.push(step.name().to_owned());
+        self.save()?;
+    }
+
+    #[throws]
+    fn clear(path: &Path) {
+        if let Err(e) = remove_file(path) {
+            if e.kind() != ErrorKind::NotFound {
+                bail!(e);
+            }
+        }
+    }
+}
+
+/// The read-only checks a release has to pass. Mutating steps such as `cargo fmt`
+/// are not gates, they always run serially.
+struct Gates {
+    parallel: bool,
+    locked: bool,
+    skip_missing: bool,
+    /// `cargo build --release`, possibly with `--all-features`.
+    build: bool,
+    build_all_features: bool,
+    /// Fail the clippy gate on any output, which `--quiet` keeps to the diagnostics.
+    strict_clippy: bool,
+    /// Every gate covers the whole workspace, see --workspace.
+    workspace: bool,
+    /// The feature flags of each `cargo check` of the feature matrix.
+    feature_matrix: Vec<Vec<String>>,
+}
+
+impl Gates {
+    /// The step label and cargo arguments of each gate.
+    fn commands(&self) -> Vec<(Step, Vec<String>)> {
+        let locked: &[_] = if self.locked { &["--locked"] } else { &[] };
+        let owned = |args: &[&[&str]]| args.concat().into_iter().map(String::from).collect();
+        let quiet: &[_] = if self.strict_clippy {
+            &["--quiet"]
+        } else {
+            &[]
+        };
+        let workspace: &[_] = if self.workspace {
+            &["--workspace"]
+        } else {
+            &[]
+        };
+        let mut commands = vec![(
+            Step::CargoClippyGate,
+            owned(&[
+                &["clippy"],
+                workspace,
+                quiet,
+                locked,
+                &["--", "-D", "warnings"],
+            ]),
+        )];
+        if self.build {
+            let features: &[_] = if self.build_all_features {
+                &["--all-features"]
+            } else {
+                &[]
+            };
+            commands.push((
+                Step::CargoBuildGate,
+                owned(&[&["build", "--release"], workspace, locked, features]),
+            ));
+        }
+        for flags in &self.feature_matrix {
+            let mut args: Vec<String> = owned(&[&["check"], workspace, locked]);
+            args.extend(flags.iter().cloned());
+            commands.push((Step::FeatureMatrixGate, args));
+        }
+        commands
+    }
+
+    /// Run every gate from `dir`. Every combination of the feature matrix, and in
+    /// parallel mode every gate, runs to completion, and each failure is reported.
+    #[throws]
+    fn run(&self, dir: &Path) {
+        let commands = self.commands();
+        if !self.parallel || commands.len() < 2 {
+            let mut failures = vec![];
+            for (step, args) in commands {
+                let result = gate(step, &args, dir, self.skip_missing, self.strict_clippy)
+                    .context(gate_step(step, &args));
+                match result {
+                    Err(e) if step == Step::FeatureMatrixGate => failures.push(e),
+                    result => result?,
+                }
+            }
+            report_failures(failures, Step::FeatureMatrixGate)?;
+            return;
+        }
+        let target = target_directory(dir).context(Step::ParallelGates)?;
+        let running: Vec<_> = commands
+            .into_iter()
+            .enumerate()
+            .map(|(i, (step, mut args))| {
+                let dir = dir.to_owned();
+                let skip_missing = self.skip_missing;
+                let strict_clippy = self.strict_clippy;
+                let label = gate_step(step, &args);
+                // Cargo locks its target directory, which would run the gates in turn.
+                if i > 0 {
+                    let own = target.join(format!("rslease-gate-{}", i));
+                    args.insert(1, "--target-dir".to_owned());
+                    args.insert(2, own.display().to_string());
+                }
+                let handle =
+                    thread::spawn(move || gate(step, &args, &dir, skip_missing, strict_clippy));
+                (label, handle)
+            })
+            .collect();
+        let mut failures = vec![];
+        for (label, handle) in running {
+            let result = handle
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("Gate thread panicked")));
+            if let Err(e) = result {
+                failures.push(e.context(label));
+            }
+        }
+        report_failures(failures, Step::ParallelGates)?;
+    }
+}
+
+/// The target directory of the crate in `dir`, as `cargo metadata` tells.
+#[throws]
+fn target_directory(dir: &Path) -> PathBuf {
+    let out = Command::new("cargo")
+        .args(["metadata", "--no-deps", "--format-version", "1"])
+        .current_dir(dir)
+        .output_success()
+        .context("`cargo metadata` failed")?;
+    let metadata = String::from_utf8(out.stdout)?;
+    let c = Regex::new(r#""target_directory":"((?:[^"\\]|\\.)*)""#)?
+        .captures(&metadata)
+        .ok_or_else(|| anyhow!("`cargo metadata` has no target_directory"))?;
+    PathBuf::from(c[1].replace(r"\\", r"\"))
+}
+
+/// The failures of gates that all ran: a single one as is, several as one error.
+#[throws]
+fn report_failures(mut failures: Vec<Error>, step: Step) {
+    if failures.len() == 1 {
+        Err(failures.remove(0))?;
+    }
+    if !failures.is_empty() {
+        let report: Vec<_> = failures.iter().map(|e| format!("{:#}", e)).collect();
+        bail!(anyhow!(report.join("\n\n")).context(step));
+    }
+}
+
+/// Run a gate from `dir`. With --strict-clippy, the clippy gate must not print anything.
+#[throws]
+fn gate(step: Step, args: &[String], dir: &Path, skip_missing: bool, strict_clippy: bool) {
+    let output = cargo_tool(args, dir, skip_missing)?;
+    if let (true, Step::CargoClippyGate, Some(output)) = (strict_clippy, step, output) {
+        let mut printed = String::from_utf8(output.stdout)?;
+        printed.push_str(&String::from_utf8(output.stderr)?);
+        if !printed.trim().is_empty() {
+            bail!(anyhow!(printed.trim().to_owned())
+                .context("--strict-clippy: clippy output should be empty"));
+        }
+    }
+}
+
+/// The error context of a gate; feature matrix gates name the failing combination.
+fn gate_step(step: Step, args: &[String]) -> String {
+    match step {
+        Step::FeatureMatrixGate => format!("{} (cargo {})", step, args.join(" ")),
+        _ => step.to_string(),
+    }
+}
+
+/// The cargo flags of each combination of the feature matrix: the given
+/// comma-separated feature lists, or by default no features, all features and each
+/// feature of the manifest alone.
+#[throws]
+fn feature_matrix(combinations: Option<Values>) -> Vec<Vec<String>> {
+    let alone = |features: &str| {
+        let mut flags = vec!["--no-default-features".to_owned()];
+        if !features.is_empty() {
+            flags.extend(["--features".to_owned(), features.to_owned()]);
+        }
+        flags
+    };
+    if let Some(combinations) = combinations {
+        return combinations.map(alone).collect();
+    }
+    let mut matrix = vec![alone(""), vec!["--all-features".to_owned()]];
+    for feature in manifest_features(&read_cargo_toml()?)? {
+        matrix.push(alone(&feature));
+    }
+    matrix
+}
+
+/// The names declared in the `[features]` table of a manifest, except `default`.
+#[throws]
+fn manifest_features(manifest: &str) -> Vec<String> {
+    let table = Regex::new(r"(?ms)^\[features\]\s*$(.*?)(?:^\[|\z)")?;
+    let name = Regex::new(r#"(?m)^\s*"?([A-Za-z0-9_\-]+)"?\s*="#)?;
+    let mut features = vec![];
+    if let Some(c) = table.captures(manifest) {
+        for n in name.captures_iter(&c[1]) {
+            if &n[1] != "default" {
+                features.push(n[1].to_owned());
+            }
+        }
+    }
+    features
+}
+
+/// Run the gates on a temporary worktree of HEAD, so that ignored or untracked
+/// files in the repo cannot hide a broken release commit.
+#[throws]
+fn verify_fresh_checkout(gates: &Gates) {
+    let dir = temp_dir().join(format!("rslease-verify-{}", process::id()));
+    Command::new("git")
+        .args(["worktree", "add", "--detach"])
+        .arg(&dir)
+        .arg("HEAD")
+        .output_success()?;
+    let verified = checkout_dir(&dir).and_then(|dir| gates.run(&dir));
+    let removed = Command::new("git")
+        .args(["worktree", "remove", "--force"])
+        .arg(&dir)
+        .output_success();
+    verified?;
+    removed?;
+}
+
+/// The directory in the worktree at `root` matching the current one, as the crate may be
+/// below the root of the repository.
+#[throws]
+fn checkout_dir(root: &Path) -> PathBuf {
+    let out = Command::new("git")
+        .args(["rev-parse", "--show-prefix"])
+        .output_success()?;
+    root.join(String::from_utf8(out.stdout)?.trim())
+}
+
+/// Append the release to the outputs of the GitHub Actions step, if run by one. The tag
+/// is empty without one.
+#[throws]
+fn github_output(version: &Version, tag: Option<&str>, previous: &Version) {
+    let path = match var("GITHUB_OUTPUT") {
+        Ok(path) if !path.is_empty() => path,
+        Ok(_) | Err(VarError::NotPresent) => return,
+        Err(e) => bail!(anyhow!(e).context("GITHUB_OUTPUT")),
+    };
+    let lines = format!(
+        "new_version={}\ntag={}\nprevious_version={}\n",
+        version,
+        tag.unwrap_or_default(),
+        previous
+    );
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .context(format!("Failed to write {}", path))?;
+}
+
+/// Push the rustdoc of `release` as a new commit of the remote `branch`. The docs are
+/// built on a temporary worktree and committed through a temporary index, so neither
+/// the working tree nor the local branches are touched.
+#[throws]
+fn publish_docs(
+    release: &str,
+    version: &Version,
+    branch: &str,
+    authoring: &Authoring,
+    push_options: Option<Values>,
+    workspace: bool,
+) {
+    let dir = temp_dir().join(format!("rslease-docs-{}", process::id()));
+    Command::new("git")
+        .args(["worktree", "add", "--detach"])
+        .arg(&dir)
+        .arg(release)
+        .output_success()?;
+    let commit = docs_commit(&dir, version, branch, authoring, workspace);
+    let removed = Command::new("git")
+        .args(["worktree", "remove", "--force"])
+        .arg(&dir)
+        .output_success();
+    let commit = commit?;
+    removed?;
+    push_command(push_options)?
+        .args(["origin", &format!("{}:refs/heads/{}", commit, branch)])
+        .output_success()?;
+}
+
+/// Build the docs in the worktree `dir`, of the whole workspace with `workspace`, and
+/// commit them on top of the remote `branch`, returning the commit.
+#[throws]
+fn docs_commit(
+    dir: &Path,
+    version: &Version,
+    branch: &str,
+    authoring: &Authoring,
+    workspace: bool,
+) -> String {
+    let crate_dir = checkout_dir(dir)?;
+    let target = dir.join("target");
+    let mut doc = Command::new("cargo");
+    doc.args(["doc", "--no-deps"]);
+    if workspace {
+        doc.arg("--workspace");
+    }
+    doc.arg("--target-dir")
+        .arg(&target)
+        .current_dir(&crate_dir)
+        .output_success()
+        .context("`cargo doc` failed")?;
+    let doc = target.join("doc");
+    // Served as is, without Jekyll ignoring the `_` files.
+    File::create(doc.join(".nojekyll"))?;
+    let mut manifest = String::new();
+    File::open(crate_dir.join("Cargo.toml"))?.read_to_string(&mut manifest)?;
+    // The crate as index, unless a virtual workspace has none.
+    if Regex::new(r"(?m)^\[package\]\s*$")?.is_match(&manifest) {
+        let name = manifest_name(&manifest)?.replace('-', "_");
+        File::create(doc.join("index.html"))?.write_all(
+            format!(
+                "<meta http-equiv=\"refresh\" content=\"0; url={}/index.html\">\n",
+                name
+            )
+            .as_bytes(),
+        )?;
+    }
+    // The branch may not exist yet, the first commit then has no parent.
+    let parent = match Command::new("git")
+        .args(["fetch", "origin", branch])
+        .output_success()
+    {
+        Ok(_) => Some(rev_parse("FETCH_HEAD")?),
+        Err(_) => None,
+    };
+    let index = target.join("rslease-docs-index");
+    let git = || {
+        let mut git = authoring.git();
+        git.env("GIT_INDEX_FILE", &index)
+            .args(["--work-tree", "."])
+            .current_dir(&doc);
+        git
+    };
+    // Cargo's build lock is not part of the docs.
+    git()
+        .args(["add", "--all", "--force", "--", ".", ":!.lock"])
+        .output_success()?;
+    let out = git().arg("write-tree").output_success()?;
+    let tree = String::from_utf8(out.stdout)?.trim().to_owned();
+    let mut commit = git();
+    commit.args(["commit-tree", &tree]);
+    if let Some(parent) = &parent {
+        commit.args(["-p", parent]);
+    }
+    if authoring.sign_commits {
+        commit.arg("-S");
+    }
+    let message = format!("Documentation for version {}.", version);
+    let out = commit.args(["-m", &message]).output_success()?;
+    String::from_utf8(out.stdout)?.trim().to_owned()
+}
+
+/// The error for a constraint that no tag satisfies, with the tags that exist and,
+/// for --for, the nearest line that has some.
+fn no_matching_tag(
+    tags: &[(Version, String)],
+    constraint: &VersionReq,
+    base: Option<&str>,
+) -> Error {
+    const SHOWN: usize = 10;
+    let mut message = format!(
+        "No matching semver tag found for constraint {}.",
+        constraint
+    );
+    if tags.is_empty() {
+        message.push_str(" There are no semver tags at all.");
+        return anyhow!(message);
+    }
+    let shown: Vec<_> = tags
+        .iter()
+        .rev()
+        .take(SHOWN)
+        .map(|(_, name)| name.as_str())
+        .collect();
+    message.push_str(&format!(
+        " Found {} semver tags, latest first: {}",
+        tags.len(),
+        shown.join(", ")
+    ));
+    if tags.len() > SHOWN {
+        message.push_str(", ...");
+    }
+    message.push('.');
+    let base = base.and_then(|base| {
+        base.split('.')
+            .map(|n| n.parse().ok())
+            .collect::<Option<Vec<u64>>>()
+    });
+    if let Some(base) = base {
+        // The line of each tag, at the precision of --for.
+        let line = |v: &Version| {
+            if base.len() == 1 {
+                vec![v.major]
+            } else {
+                vec![v.major, v.minor]
+            }
+        };
+        let distance = |l: &Vec<u64>| -> Vec<u64> {
+            l.iter()
+                .zip(&base)
+                .map(|(a, b)| a.max(b) - a.min(b))
+                .collect()
+        };
+        if let Some(nearest) = tags.iter().map(|(v, _)| line(v)).min_by_key(distance) {
+            let nearest: Vec<_> = nearest.iter().map(u64::to_string).collect();
+            message.push_str(&format!(" Did you mean --for {}?", nearest.join(".")));
+        }
+    }
+    anyhow!(message)
+}
+
+/// The semver tags, as versions with their tag names, oldest first. A version tagged
+/// more than once, e.g. locally and on the remote, keeps its first name.
+#[throws]
+fn semver_tags(
+    template: &TagTemplate,
+    include_remote: bool,
+    prereleases: bool,
+) -> Vec<(Version, String)> {
+    let mut names = local_tags()?;
+    if include_remote {
+        names.extend(remote_tags()?);
+    }
+    let mut semver_tags = vec![];
+    let semver_tag_re = template.regex(prereleases)?;
+    for line in &names {
+        if let Some(c) = semver_tag_re.captures(line) {
+            let version = Version::parse(&c[1])
+                .context(format!("Tag {} has no valid version in its capture", line))?;
+            // A --tag-filter cannot tell prereleases apart.
+            if prereleases || !version.is_prerelease() {
+                semver_tags.push((version, line.clone()));
+            }
+        }
+    }
+    // Stable, so that the local names come first.
+    semver_tags.sort_by(|(a, _), (b, _)| a.cmp(b));
+    semver_tags.dedup_by(|(a, _), (b, _)| a == b);
+    semver_tags
+}
+
+/// Whether `version` satisfies the base constraint. Prereleases belong to the line of
+/// their x.y.z, which VersionReq would not say.
+fn in_line(version: &Version, constraint: &VersionReq) -> bool {
+    let mut version = version.clone();
+    version.pre.clear();
+    constraint.matches(&version)
+}
+
+/// The latest of `tags` satisfying the base constraint, see `in_line`.
+fn latest_in_line<'a>(
+    tags: &'a [(Version, String)],
+    constraint: &VersionReq,
+) -> Option<&'a (Version, String)> {
+    tags.iter()
+        .filter(|(v, _)| in_line(v, constraint))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+}
+
+/// The message of an annotated tag, without its signature, or None for a lightweight tag.
+#[throws]
+fn tag_message(tag: &str) -> Option<String> {
+    let out = Command::new("git")
+        .args(["cat-file", "-t", tag])
+        .output_success()?;
+    if String::from_utf8(out.stdout)?.trim() != "tag" {
+        return None;
+    }
+    let out = Command::new("git")
+        .args([
+            "tag",
+            "--list",
+            "--format=%(contents:subject)%0a%0a%(contents:body)",
+            tag,
+        ])
+        .output_success()?;
+    Some(String::from_utf8(out.stdout)?.trim().to_owned())
+}
+
+#[throws]
+fn local_tags() -> Vec<String> {
+    let out = Command::new("git")
+        .args(["tag", "--list"])
+        .output_success()?;
+    String::from_utf8(out.stdout)?
+        .lines()
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// The tags of origin, without the peeled `^{}` entries of annotated tags.
+#[throws]
+fn remote_tags() -> Vec<String> {
+    let out = Command::new("git")
+        .args(["ls-remote", "--tags", "origin"])
+        .output_success()
+        .context("Failed to list remote tags")?;
+    String::from_utf8(out.stdout)?
+        .lines()
+        .filter_map(|line| line.split_once("\trefs/tags/"))
+        .map(|(_, name)| name)
+        .filter(|name| !name.ends_with("^{}"))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// The names among `tags` of development versions, for --prune-dev-tags: prereleases
+/// with a `dev` identifier, not the published ones such as `-rc.1`.
+#[throws]
+fn dev_tags(template: &TagTemplate, tags: Vec<String>) -> Vec<String> {
+    let semver_tag_re = template.regex(true)?;
+    let mut names = vec![];
+    for name in tags {
+        let prerelease = match semver_tag_re.captures(&name) {
+            Some(c) => Version::parse(&c[1]).is_ok_and(|v| v.pre.contains(&dev_identifier())),
+            None => false,
+        };
+        if prerelease {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// How versions map to tag names: whatever surrounds the `{version}` placeholder.
+struct TagTemplate {
+    prefix: String,
+    suffix: String,
+    /// --tag-filter, replacing the template for tag discovery.
+    filter: Option<Regex>,
+}
+
+impl TagTemplate {
+    #[throws]
+    fn parse(template: &str) -> Self {
+        let parts: Vec<_> = template.split("{version}").collect();
+        if parts.len() != 2 {
+            bail!("--tag-template: must contain `{version}` exactly once.");
+        }
+        TagTemplate {
+            prefix: parts[0].to_owned(),
+            suffix: parts[1].to_owned(),
+            filter: None,
+        }
+    }
+
+    /// Discover the tags matching `filter` instead, its first group being the version.
+    #[throws]
+    fn filter(self, filter: &str) -> Self {
+        let filter = Regex::new(filter).context("--tag-filter: invalid regex")?;
+        if filter.captures_len() < 2 {
+            bail!("--tag-filter: must capture the version in a group.");
+        }
+        TagTemplate {
+            filter: Some(filter),
+            ..self
+        }
+    }
+
+    fn name(&self, version: impl Display) -> String {
+        format!("{}{}{}", self.prefix, version, self.suffix)
+    }
+
+    /// Matches tag names for plain x.y.z versions, or prereleases too, capturing the
+    /// version.
+    #[throws]
+    fn regex(&self, prereleases: bool) -> Regex {
+        if let Some(filter) = &self.filter {
+            return filter.clone();
+        }
+        let pre = if prereleases {
+            r"(?:-[0-9A-Za-z.-]+)?"
+        } else {
+            ""
+        };
+        Regex::new(&format!(
+            r"^{}(\d+\.\d+\.\d+{}){}$",
+            regex::escape(&self.prefix),
+            pre,
+            regex::escape(&self.suffix)
+        ))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bump_version_replaces_the_first_version() {
+        let manifest = "[package]\nversion = \"1.2.0-dev\"\n\n[dependencies]\nversion = \"1\"\n";
+        assert_eq!(
+            bump_version(manifest, &Version::parse("1.2.0").unwrap()).unwrap(),
+            "[package]\nversion = \"1.2.0\"\n\n[dependencies]\nversion = \"1\"\n"
+        );
+        assert!(bump_version("[package]\n", &Version::parse("1.2.0").unwrap()).is_err());
+    }
+
+    #[test]
+    fn unified_diff_shows_the_changed_lines_in_context() {
+        assert_eq!(unified_diff("f", "a\nb\n", "a\nb\n"), "");
+        assert_eq!(
+            unified_diff("f", "a\nb\nc\n", "a\nB\nc\n"),
+            "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+        assert_eq!(
+            unified_diff("f", "a\n", "a\nb\n"),
+            "--- a/f\n+++ b/f\n@@ -1,1 +1,2 @@\n a\n+b\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_splits_distant_changes_into_hunks() {
+        let old: String = (1..=20).map(|n| format!("{}\n", n)).collect();
+        let new: String = (1..=20)
+            .map(|n| match n {
+                2 => "two\n".to_owned(),
+                19 => "nineteen\n".to_owned(),
+                n => format!("{}\n", n),
+            })
+            .collect();
+        assert_eq!(
+            unified_diff("f", &old, &new),
+            "--- a/f\n+++ b/f\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -16,5 +16,5 @@\n 16\n 17\n 18\n-19\n+nineteen\n 20\n"
+        );
+    }
+
+    #[test]
+    fn tag_template_names_and_matches_versions() {
+        let template = TagTemplate::parse("cli-v{version}").unwrap();
+        assert_eq!(template.name("1.2.3"), "cli-v1.2.3");
+        let plain = template.regex(false).unwrap();
+        assert_eq!(&plain.captures("cli-v1.2.3").unwrap()[1], "1.2.3");
+        assert!(!plain.is_match("cli-v1.2.3-rc.1"));
+        assert!(!plain.is_match("v1.2.3"));
+        assert!(!plain.is_match("cli-v1.2.3.4"));
+        let prereleases = template.regex(true).unwrap();
+        assert_eq!(
+            &prereleases.captures("cli-v1.2.3-rc.1").unwrap()[1],
+            "1.2.3-rc.1"
+        );
+    }
+
+    #[test]
+    fn tag_template_has_one_placeholder() {
+        assert!(TagTemplate::parse("v").is_err());
+        assert!(TagTemplate::parse("{version}-{version}").is_err());
+    }
+
+    #[test]
+    fn tag_filter_replaces_the_template_for_discovery() {
+        let template = TagTemplate::parse("v{version}")
+            .unwrap()
+            .filter(r"^cli-(\d+\.\d+\.\d+)$")
+            .unwrap();
+        assert_eq!(
+            &template
+                .regex(false)
+                .unwrap()
+                .captures("cli-1.2.3")
+                .unwrap()[1],
+            "1.2.3"
+        );
+        assert!(!template.regex(false).unwrap().is_match("v1.2.3"));
+        assert_eq!(template.name("1.2.3"), "v1.2.3");
+        let template = TagTemplate::parse("v{version}").unwrap();
+        assert!(template.filter(r"^cli-\d+$").is_err());
+        let template = TagTemplate::parse("v{version}").unwrap();
+        assert!(template.filter(r"^cli-(").is_err());
+    }
+
+    #[test]
+    fn glob_regex_matches_whole_branch_names() {
+        let glob = |glob: &str, name: &str| glob_regex(glob).unwrap().is_match(name);
+        assert!(glob("main", "main"));
+        assert!(!glob("main", "main2"));
+        assert!(glob("release/*", "release/1.2"));
+        assert!(!glob("release/*", "release/1/2"));
+        assert!(glob("release/**", "release/1/2"));
+        assert!(glob("v?", "v1"));
+        assert!(!glob("v?", "v12"));
+        assert!(!glob("1.x", "1yx"));
+    }
+
+    #[test]
+    fn prerelease_version_bumps_the_last_number() {
+        let bump = |latest: &str| {
+            prerelease_version(&Version::parse(latest).unwrap())
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(bump("1.0.0-rc.1"), "1.0.0-rc.2");
+        assert_eq!(bump("1.0.0-rc.9"), "1.0.0-rc.10");
+        assert_eq!(bump("1.0.0-1.beta"), "1.0.0-2.beta");
+        assert_eq!(bump("1.0.0-beta"), "1.0.0-beta.1");
+        assert!(prerelease_version(&Version::parse("1.0.0").unwrap()).is_err());
+    }
+
+    #[test]
+    fn unreleased_entries_counts_the_unreleased_section_only() {
+        let changelog = "# Changelog\n\
+                         \n\
+                         ## [Unreleased]\n\
+                         ### Added\n\
+                         - Pagination.\n\
+                         \n\
+                         ### Fixed\n\
+                         - Crash on empty input.\n\
+                         \n\
+                         ## [1.0.0] - 2026-01-01\n\
+                         - First release.\n";
+        assert_eq!(unreleased_entries(changelog).unwrap(), Some(2));
+        let empty = "# Changelog\n\n## [Unreleased]\n\n## [1.0.0] - 2026-01-01\n- First release.\n";
+        assert_eq!(unreleased_entries(empty).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn unreleased_entries_stops_at_the_links() {
+        let changelog = "## unreleased\n\
+                         \n\
+                         [unreleased]: https://example.com/compare/v1.0.0...HEAD\n\
+                         [1.0.0]: https://example.com/releases/v1.0.0\n";
+        assert_eq!(unreleased_entries(changelog).unwrap(), Some(0));
+        assert_eq!(unreleased_entries("# Changelog\n- Entry.\n").unwrap(), None);
+    }
+
+    #[test]
+    fn shell_quote_quotes_only_when_needed() {
+        assert_eq!(shell_quote("--target-dir=./a/b"), "--target-dir=./a/b");
+        assert_eq!(shell_quote("v1.0.0"), "v1.0.0");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("two words"), "'two words'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn released_changelog_dates_the_release_and_links_it() {
+        let changelog = "# Changelog\n\
+                         \n\
+                         ## [Unreleased]\n\
+                         - Pagination.\n\
+                         \n\
+                         ## [1.0.0] - 2026-01-01\n\
+                         - First release.\n\
+                         \n\
+                         [Unreleased]: https://example.com/r/compare/v1.0.0...HEAD\n\
+                         [1.0.0]: https://example.com/r/releases/v1.0.0\n";
+        let version = Version::parse("1.1.0").unwrap();
+        let released = released_changelog(changelog, &version, "v1.1.0", "2026-02-03").unwrap();
+        assert_eq!(
+            released.as_deref(),
+            Some(
+                "# Changelog\n\
+                 \n\
+                 ## [Unreleased]\n\
+                 \n\
+                 ## [1.1.0] - 2026-02-03\n\
+                 - Pagination.\n\
+                 \n\
+                 ## [1.0.0] - 2026-01-01\n\
+                 - First release.\n\
+                 \n\
+                 [Unreleased]: https://example.com/r/compare/v1.1.0...HEAD\n\
+                 [1.1.0]: https://example.com/r/compare/v1.0.0...v1.1.0\n\
+                 [1.0.0]: https://example.com/r/releases/v1.0.0\n"
+            )
+        );
+        assert_eq!(
+            released_changelog("# Changelog\n", &version, "v1.1.0", "2026-02-03").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn increment_applies_its_operations_in_order() {
+        let increment = |from: &str, expression: &str| {
+            let from = Version::parse(from).unwrap();
+            apply_increment(&from, &parse_increment(expression).unwrap()).map(|v| v.to_string())
+        };
+        assert_eq!(
+            increment("1.2.3", "minor+1,patch=0,pre=rc.1").unwrap(),
+            "1.3.0-rc.1"
+        );
+        assert_eq!(increment("1.2.3", "minor+1").unwrap(), "1.3.3");
+        assert_eq!(
+            increment("1.2.3", " major + 2 , minor = 0 ").unwrap(),
+            "3.0.3"
+        );
+        assert_eq!(increment("1.3.0-rc.1", "pre=").unwrap(), "1.3.0");
+        assert!(increment("1.3.0-rc.1", "pre=rc.0").is_err());
+        assert!(increment("1.2.3", "patch=3").is_err());
+        assert!(increment("1.2.3", &format!("major+{}", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn increment_rejects_invalid_operations() {
+        for expression in &[
+            "minor",
+            "minor+x",
+            "minor-1",
+            "pre+1",
+            "pre=rc..1",
+            "build=1",
+            "epoch+1",
+            "minor+1,",
+        ] {
+            assert!(parse_increment(expression).is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn release_state_round_trips_and_requires_its_versions() {
+        let path = temp_dir().join(format!("rslease-state-test-{}", process::id()));
+        let version = |v| Version::parse(v).unwrap();
+        let mut state = ReleaseState {
+            path: path.clone(),
+            new_version: version("1.3.0"),
+            manifest_version: version("1.3.0+sha.abc"),
+            post_version: Some(version("1.4.0-dev")),
+            base_version: version("1.2.0"),
+            release_commit: None,
+            done: vec![],
+        };
+        state.record(Step::Bump).unwrap();
+        let loaded = ReleaseState::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.manifest_version, state.manifest_version);
+        assert_eq!(loaded.post_version, state.post_version);
+        assert_eq!(loaded.base_version, state.base_version);
+        assert!(loaded.is_done(Step::Bump) && !loaded.is_done(Step::ReleaseCommit));
+        File::create(&path)
+            .unwrap()
+            .write_all(b"version 1.3.0\nmanifest 1.3.0\n")
+            .unwrap();
+        assert!(ReleaseState::load(&path).is_err());
+        ReleaseState::clear(&path).unwrap();
+        assert!(ReleaseState::load(&path).unwrap().is_none());
     }
-    let manifest = re.replace(&manifest, |c: &Captures| {
-        format!("{}{}{}", &c[1], version, &c[2])
-    });
-    File::create("Cargo.toml")?.write_all(manifest.as_bytes())?;
 }
@@ -0,0 +1,687 @@
+//! Releases of throwaway git repositories, running the binary as a user would.
+
+use std::env::temp_dir;
+use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+
+/// A git repository in a temporary directory, removed on drop. Its `origin`, once
+/// published, is a bare repository next to it.
+struct Repo {
+    root: PathBuf,
+}
+
+impl Repo {
+    fn new(name: &str) -> Self {
+        let root = temp_dir().join(format!("rslease-test-{}-{}", process::id(), name));
+        if root.exists() {
+            remove_dir_all(&root).unwrap();
+        }
+        create_dir_all(root.join("work")).unwrap();
+        let repo = Repo { root };
+        repo.git(&["init", "--quiet", "--initial-branch=main"]);
+        repo
+    }
+
+    fn work(&self) -> PathBuf {
+        self.root.join("work")
+    }
+
+    fn write(&self, path: &str, contents: &str) {
+        let path = self.work().join(path);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, contents).unwrap();
+    }
+
+    /// Run git in the working tree, returning its trimmed stdout.
+    fn git(&self, args: &[&str]) -> String {
+        run(hermetic(&mut Command::new("git"))
+            .args(args)
+            .current_dir(self.work()))
+    }
+
+    fn commit(&self, message: &str) {
+        self.git(&["add", "--all"]);
+        self.git(&["commit", "--quiet", "-m", message]);
+    }
+
+    /// Write the Cargo.lock of the crate or workspace in `dir`.
+    fn lock(&self, dir: &str) {
+        run(hermetic(&mut Command::new("cargo"))
+            .args(["generate-lockfile", "--offline", "--quiet"])
+            .current_dir(self.work().join(dir)));
+    }
+
+    /// Give the repository an `origin` that `main` tracks.
+    fn publish(&self) {
+        let origin = self.root.join("origin.git");
+        let origin = origin.to_str().unwrap();
+        self.git(&["clone", "--quiet", "--bare", ".", origin]);
+        self.git(&["remote", "add", "origin", origin]);
+        self.git(&["fetch", "--quiet", "origin"]);
+        self.git(&["branch", "--quiet", "--set-upstream-to=origin/main"]);
+    }
+
+    /// Push a commit to `origin` from another clone, leaving this one behind.
+    fn push_elsewhere(&self) {
+        let other = self.root.join("other");
+        run(hermetic(&mut Command::new("git"))
+            .args(["clone", "--quiet", "origin.git", "other"])
+            .current_dir(&self.root));
+        write(other.join("README"), "Elsewhere.\n").unwrap();
+        for args in [
+            &["add", "README"][..],
+            &["commit", "--quiet", "-m", "Elsewhere."],
+            &["push", "--quiet", "origin", "main"],
+        ] {
+            run(hermetic(&mut Command::new("git"))
+                .args(args)
+                .current_dir(&other));
+        }
+    }
+
+    fn rslease(&self, args: &[&str]) -> Output {
+        hermetic(&mut Command::new(env!("CARGO_BIN_EXE_rslease")))
+            .env_remove("RSLEASE_VERSION")
+            .env_remove("GITHUB_OUTPUT")
+            .args(args)
+            .current_dir(self.work())
+            .output()
+            .unwrap()
+    }
+
+    /// The `version` of the manifest at `path` in `rev`.
+    fn version_at(&self, rev: &str, path: &str) -> String {
+        manifest_version(&self.git(&["show", &format!("{}:{}", rev, path)]))
+    }
+
+    fn tags(&self) -> Vec<String> {
+        let tags = self.git(&["tag", "--list"]);
+        tags.lines().map(ToOwned::to_owned).collect()
+    }
+
+    /// The subjects of the last `n` commits, latest first.
+    fn subjects(&self, n: usize) -> Vec<String> {
+        let log = self.git(&["log", &format!("-{}", n), "--format=%s"]);
+        log.lines().map(ToOwned::to_owned).collect()
+    }
+}
+
+impl Drop for Repo {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.root);
+    }
+}
+
+/// Keep the user's git configuration, e.g. commit signing, out of the tests.
+fn hermetic(command: &mut Command) -> &mut Command {
+    command
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+}
+
+fn run(command: &mut Command) -> String {
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        command,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap().trim().to_owned()
+}
+
+fn package(name: &str, version: &str) -> String {
+    format!(
+        "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2021\"\n",
+        name, version
+    )
+}
+
+fn manifest_version(manifest: &str) -> String {
+    let line = manifest
+        .lines()
+        .find(|line| line.starts_with("version = "))
+        .expect("no version");
+    line.trim_start_matches("version = ")
+        .trim_matches('"')
+        .to_owned()
+}
+
+/// A crate in `dir` whose `released` version is tagged, followed by the `dev` version
+/// and a change to release.
+fn released_crate(name: &str, dir: &str, released: &str, dev: &str) -> Repo {
+    let repo = Repo::new(name);
+    let path = |file: &str| Path::new(dir).join(file).to_str().unwrap().to_owned();
+    repo.write(".gitignore", "target/\n");
+    repo.write(&path("src/main.rs"), "fn main() {}\n");
+    repo.write(&path("Cargo.toml"), &package("fixture", released));
+    repo.lock(dir);
+    repo.commit(&format!("Release version {}.", released));
+    repo.git(&["tag", &format!("v{}", released)]);
+    repo.write(&path("Cargo.toml"), &package("fixture", dev));
+    repo.lock(dir);
+    repo.commit("Post-release.");
+    repo.write(
+        &path("src/main.rs"),
+        "fn main() {\n    println!(\"Hello.\");\n}\n",
+    );
+    repo.commit("Greet.");
+    repo
+}
+
+fn succeeded(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "rslease failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn failed_with(output: &Output, message: &str) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "rslease succeeded");
+    assert!(stderr.contains(message), "unexpected error: {}", stderr);
+}
+
+#[test]
+fn release_is_committed_tagged_and_pushed() {
+    let repo = released_crate("happy", ".", "0.1.0", "0.2.0-dev");
+    repo.publish();
+    succeeded(&repo.rslease(&[]));
+    assert_eq!(repo.version_at("v0.2.0", "Cargo.toml"), "0.2.0");
+    assert_eq!(repo.version_at("HEAD", "Cargo.toml"), "0.3.0-dev");
+    assert_eq!(
+        repo.subjects(2),
+        ["Post-release.", "Release version 0.2.0."]
+    );
+    assert_eq!(repo.git(&["status", "--porcelain"]), "");
+    assert_eq!(
+        repo.git(&["rev-parse", "origin/main"]),
+        repo.git(&["rev-parse", "HEAD"])
+    );
+    let remote_tags = repo.git(&["ls-remote", "--tags", "origin", "v0.2.0"]);
+    assert!(remote_tags.ends_with("refs/tags/v0.2.0"), "{}", remote_tags);
+}
+
+#[test]
+fn dirty_tree_is_not_released() {
+    let repo = released_crate("dirty", ".", "0.1.0", "0.2.0-dev");
+    repo.write("src/main.rs", "fn main() {}\n");
+    failed_with(&repo.rslease(&["--local"]), "repo not clean");
+    assert_eq!(repo.tags(), ["v0.1.0"]);
+    assert_eq!(repo.subjects(1), ["Greet."]);
+}
+
+#[test]
+fn branch_behind_upstream_is_not_released() {
+    let repo = released_crate("behind", ".", "0.1.0", "0.2.0-dev");
+    repo.publish();
+    repo.push_elsewhere();
+    failed_with(&repo.rslease(&[]), "repo behind upstream");
+    assert_eq!(repo.tags(), ["v0.1.0"]);
+    assert_eq!(repo.subjects(1), ["Greet."]);
+}
+
+#[test]
+fn existing_version_is_not_released_again() {
+    let repo = released_crate("exists", ".", "0.1.0", "0.2.0-dev");
+    failed_with(
+        &repo.rslease(&["--local", "--set-version", "0.1.0"]),
+        "a version that already exists: 0.1.0",
+    );
+    assert_eq!(repo.tags(), ["v0.1.0"]);
+    assert_eq!(repo.version_at("HEAD", "Cargo.toml"), "0.2.0-dev");
+}
+
+/// The 1.0 line branched off its release, with a fix to release as 1.0.1.
+fn maintenance_branch(name: &str) -> Repo {
+    let repo = released_crate(name, ".", "1.0.0", "1.1.0-dev");
+    repo.git(&["checkout", "--quiet", "-b", "1.0", "v1.0.0"]);
+    repo.write("src/main.rs", "fn main() {\n    println!(\"Fixed.\");\n}\n");
+    repo.commit("Fix.");
+    repo
+}
+
+#[test]
+fn maintenance_patch_stays_on_its_line() {
+    let repo = maintenance_branch("maintenance");
+    repo.git(&["tag", "v1.1.0", "main"]);
+    succeeded(&repo.rslease(&["--local", "--patch", "--for", "1.0"]));
+    assert_eq!(repo.tags(), ["v1.0.0", "v1.0.1", "v1.1.0"]);
+    assert_eq!(repo.version_at("v1.0.1", "Cargo.toml"), "1.0.1");
+    assert_eq!(repo.subjects(2), ["Release version 1.0.1.", "Fix."]);
+}
+
+#[test]
+fn maintenance_patch_never_gets_a_development_version() {
+    // Without a 1.1.0 tag, the next minor does not exist, which used to be the only
+    // thing preventing a 1.1.0-dev on the 1.0 line.
+    let repo = maintenance_branch("maintenance-untagged");
+    succeeded(&repo.rslease(&["--local", "--patch", "--for", "1.0"]));
+    assert_eq!(repo.version_at("HEAD", "Cargo.toml"), "1.0.1");
+    assert_eq!(repo.subjects(1), ["Release version 1.0.1."]);
+    assert_eq!(repo.version_at("main", "Cargo.toml"), "1.1.0-dev");
+}
+
+#[test]
+fn strip_dev_on_base_releases_the_development_version() {
+    // A minor release from v1.2.0 would be 1.3.0.
+    let repo = released_crate("strip-dev", ".", "1.2.0", "2.0.0-dev");
+    succeeded(&repo.rslease(&["--local", "--strip-dev-on-base"]));
+    assert_eq!(repo.tags(), ["v1.2.0", "v2.0.0"]);
+    assert_eq!(repo.version_at("v2.0.0", "Cargo.toml"), "2.0.0");
+    assert_eq!(repo.version_at("HEAD", "Cargo.toml"), "2.1.0-dev");
+}
+
+#[test]
+fn strip_dev_on_base_needs_a_development_version() {
+    let repo = released_crate("strip-dev-released", ".", "1.2.0", "1.3.0");
+    failed_with(
+        &repo.rslease(&["--local", "--strip-dev-on-base"]),
+        "version 1.3.0 is not a -dev prerelease",
+    );
+    assert_eq!(repo.tags(), ["v1.2.0"]);
+}
+
+/// The version a --dry-run release would have.
+fn would_release(repo: &Repo, args: &[&str]) -> String {
+    let mut args = args.to_vec();
+    args.extend(["--local", "--dry-run"]);
+    let stdout = succeeded(&repo.rslease(&args));
+    let line = stdout.lines().next().unwrap_or_default();
+    let version = line.strip_prefix("Would release ").expect(line);
+    version.split(' ').next().unwrap().to_owned()
+}
+
+#[test]
+fn zerover_shifts_increments_below_1_0_0() {
+    let repo = released_crate("zerover", ".", "0.2.0", "0.3.0-dev");
+    assert_eq!(would_release(&repo, &["--zerover", "--major"]), "0.3.0");
+    assert_eq!(would_release(&repo, &["--zerover"]), "0.2.1");
+    assert_eq!(would_release(&repo, &["--zerover", "--patch"]), "0.2.1");
+    assert_eq!(would_release(&repo, &["--major"]), "1.0.0");
+    assert_eq!(would_release(&repo, &[]), "0.3.0");
+}
+
+#[test]
+fn zerover_is_plain_semver_from_1_0_0() {
+    let repo = released_crate("zerover-stable", ".", "1.2.0", "1.3.0-dev");
+    assert_eq!(would_release(&repo, &["--zerover", "--major"]), "2.0.0");
+    assert_eq!(would_release(&repo, &["--zerover"]), "1.3.0");
+    assert_eq!(would_release(&repo, &["--zerover", "--patch"]), "1.2.1");
+}
+
+#[test]
+fn zerover_release_is_committed_with_its_development_version() {
+    let repo = released_crate("zerover-release", ".", "0.2.0", "0.3.0-dev");
+    succeeded(&repo.rslease(&["--local", "--zerover", "--major"]));
+    assert_eq!(repo.version_at("v0.3.0", "Cargo.toml"), "0.3.0");
+    assert_eq!(repo.version_at("HEAD", "Cargo.toml"), "0.4.0-dev");
+}
+
+/// The `version` of package `name` in the Cargo.lock at `path` in `rev`.
+fn locked_version(repo: &Repo, rev: &str, path: &str, name: &str) -> String {
+    let lock = repo.git(&["show", &format!("{}:{}", rev, path)]);
+    let entry = format!("name = \"{}\"\n", name);
+    let after = &lock[lock.find(&entry).expect("not locked") + entry.len()..];
+    manifest_version(after)
+}
+
+#[test]
+fn crate_in_a_subdirectory_is_released_from_the_root() {
+    let repo = released_crate("subdirectory", "crate", "0.1.0", "0.2.0-dev");
+    succeeded(&repo.rslease(&["--local", "--repo", "crate", "--commit-paths"]));
+    assert_eq!(repo.tags(), ["v0.1.0", "v0.2.0"]);
+    assert_eq!(repo.version_at("v0.2.0", "crate/Cargo.toml"), "0.2.0");
+    assert_eq!(
+        locked_version(&repo, "v0.2.0", "crate/Cargo.lock", "fixture"),
+        "0.2.0"
+    );
+    assert_eq!(repo.version_at("HEAD", "crate/Cargo.toml"), "0.3.0-dev");
+    assert_eq!(repo.git(&["status", "--porcelain"]), "");
+}
+
+/// A workspace with its member in `crate/`, 0.1.0 tagged and a change to release.
+fn workspace_member(name: &str) -> Repo {
+    let repo = Repo::new(name);
+    repo.write(".gitignore", "target/\n");
+    repo.write("Cargo.toml", "[workspace]\nmembers = [\"crate\"]\n");
+    repo.write("crate/src/main.rs", "fn main() {}\n");
+    repo.write("crate/Cargo.toml", &package("fixture", "0.1.0"));
+    repo.lock(".");
+    repo.commit("Release version 0.1.0.");
+    repo.git(&["tag", "v0.1.0"]);
+    repo.write(
+        "crate/src/main.rs",
+        "fn main() {\n    println!(\"Hello.\");\n}\n",
+    );
+    repo.commit("Greet.");
+    repo
+}
+
+#[test]
+fn workspace_member_commits_the_lockfile_of_its_workspace() {
+    let repo = workspace_member("member");
+    succeeded(&repo.rslease(&["--local", "--repo", "crate", "--commit-paths"]));
+    assert_eq!(
+        locked_version(&repo, "v0.2.0", "Cargo.lock", "fixture"),
+        "0.2.0"
+    );
+    assert_eq!(
+        locked_version(&repo, "HEAD", "Cargo.lock", "fixture"),
+        "0.3.0-dev"
+    );
+    assert_eq!(repo.git(&["status", "--porcelain"]), "");
+}
+
+const MEMBERS: [&str; 3] = ["alpha", "beta", "gamma"];
+
+/// A workspace whose three members inherit the version of [workspace.package], with
+/// 0.1.0 tagged, 0.2.0-dev after it and a change to release.
+fn lockstep_workspace(name: &str) -> Repo {
+    let repo = Repo::new(name);
+    let root = |version: &str| {
+        format!(
+            "[workspace]\nmembers = [\"alpha\", \"beta\", \"gamma\"]\nresolver = \"2\"\n\n\
+             [workspace.package]\nversion = \"{}\"\nedition = \"2021\"\n",
+            version
+        )
+    };
+    repo.write(".gitignore", "target/\n");
+    for member in MEMBERS {
+        repo.write(
+            &format!("{}/Cargo.toml", member),
+            &format!(
+                "[package]\nname = \"{}\"\nversion.workspace = true\nedition.workspace = true\n",
+                member
+            ),
+        );
+        repo.write(&format!("{}/src/main.rs", member), "fn main() {}\n");
+    }
+    repo.write("Cargo.toml", &root("0.1.0"));
+    repo.lock(".");
+    repo.commit("Release version 0.1.0.");
+    repo.git(&["tag", "v0.1.0"]);
+    repo.write("Cargo.toml", &root("0.2.0-dev"));
+    repo.lock(".");
+    repo.commit("Post-release.");
+    repo.write(
+        "beta/src/main.rs",
+        "fn main() {\n    println!(\"Hello.\");\n}\n",
+    );
+    repo.commit("Greet.");
+    repo
+}
+
+#[test]
+fn lockstep_workspace_is_released_with_one_tag() {
+    let repo = lockstep_workspace("lockstep");
+    succeeded(&repo.rslease(&["--local", "--workspace"]));
+    assert_eq!(repo.tags(), ["v0.1.0", "v0.2.0"]);
+    assert_eq!(repo.version_at("v0.2.0", "Cargo.toml"), "0.2.0");
+    assert_eq!(repo.version_at("HEAD", "Cargo.toml"), "0.3.0-dev");
+    for member in MEMBERS {
+        assert_eq!(
+            locked_version(&repo, "v0.2.0", "Cargo.lock", member),
+            "0.2.0"
+        );
+        // The members inherit the version, their manifests are left alone.
+        let manifest = format!("{}/Cargo.toml", member);
+        assert_eq!(repo.git(&["diff", "v0.1.0", "HEAD", "--", &manifest]), "");
+    }
+}
+
+#[test]
+fn lockstep_workspace_gates_cover_every_member() {
+    let repo = lockstep_workspace("lockstep-plan");
+    let plan = succeeded(&repo.rslease(&[
+        "--local",
+        "--workspace",
+        "--build",
+        "--plan",
+        "--dump-commands",
+    ]));
+    for command in [
+        "cargo clippy --workspace",
+        "cargo build --release --workspace",
+        "cargo fmt --all",
+    ] {
+        assert!(plan.contains(command), "no `{}` in:\n{}", command, plan);
+    }
+}
+
+#[test]
+fn lockstep_workspace_needs_the_shared_version_first() {
+    let repo = lockstep_workspace("lockstep-package");
+    let manifest = repo.git(&["show", "HEAD:Cargo.toml"]);
+    repo.write(
+        "Cargo.toml",
+        &format!("{}\n{}", package("root", "0.0.0"), manifest),
+    );
+    repo.commit("Add a root package.");
+    failed_with(
+        &repo.rslease(&["--local", "--workspace"]),
+        "must belong to [workspace.package]",
+    );
+}
+
+/// A crate released as 0.1.0 under the former tag naming, `rel/0.1.0`, and as
+/// `v`-prefixed prereleases since.
+fn renamed_tags(name: &str) -> Repo {
+    let repo = released_crate(name, ".", "0.1.0", "0.2.0-dev");
+    repo.git(&["tag", "--delete", "v0.1.0"]);
+    repo.git(&["tag", "rel/0.1.0", "HEAD~2"]);
+    repo.git(&["tag", "v0.2.0-rc.1", "HEAD~1"]);
+    repo
+}
+
+#[test]
+fn tag_filter_finds_the_base_by_its_own_name() {
+    let repo = renamed_tags("tag-filter");
+    let filter = r"^(?:rel/|v)(.+)$";
+    assert_eq!(would_release(&repo, &["--tag-filter", filter]), "0.2.0");
+    repo.git(&["tag", "--force", "rel/0.1.0", "HEAD"]);
+    let output = repo.rslease(&[
+        "--dry-run",
+        "--local",
+        "--tag-filter",
+        filter,
+        "--check-releasable",
+    ]);
+    failed_with(&output, "No commits since rel/0.1.0");
+    assert_eq!(output.status.code(), Some(10));
+}
+
+#[test]
+fn list_tags_prints_the_names_found() {
+    let repo = renamed_tags("list-tags");
+    let stdout = succeeded(&repo.rslease(&[
+        "--list-tags",
+        "--prerelease",
+        "--tag-filter",
+        r"^(?:rel/|v)(.+)$",
+    ]));
+    assert_eq!(stdout, "rel/0.1.0\nv0.2.0-rc.1 (latest)\n");
+}
+
+#[test]
+fn missing_line_lists_the_names_found() {
+    let repo = renamed_tags("missing-line");
+    failed_with(
+        &repo.rslease(&["--local", "--for", "1", "--tag-filter", r"^(?:rel/|v)(.+)$"]),
+        "Found 1 semver tags, latest first: rel/0.1.0",
+    );
+}
+
+/// The options of a release whose post-bump command fails until `../fixed` exists.
+const INTERRUPTED: [&str; 6] = [
+    "--local",
+    "--keepachangelog",
+    "--backup",
+    "--keep-backup",
+    "--post-bump-command",
+    "test -e ../fixed",
+];
+
+/// A crate with a changelog, released with INTERRUPTED up to the failure, then fixed.
+fn interrupted_release(name: &str) -> Repo {
+    let repo = released_crate(name, ".", "0.1.0", "0.2.0-dev");
+    repo.write(".gitignore", "target/\n*.bak\n");
+    repo.write(
+        "CHANGELOG.md",
+        "# Changelog\n\n## [Unreleased]\n- Greeting.\n\n## [0.1.0] - 2026-01-01\n- Start.\n",
+    );
+    repo.commit("Changelog.");
+    failed_with(&repo.rslease(&INTERRUPTED), "step: post-bump command");
+    write(repo.root.join("fixed"), "").unwrap();
+    repo
+}
+
+#[test]
+fn resumed_release_updates_the_changelog_once() {
+    let repo = interrupted_release("resume-changelog");
+    succeeded(&repo.rslease(&[&INTERRUPTED[..], &["--resume"]].concat()));
+    let changelog = repo.git(&["show", "v0.2.0:CHANGELOG.md"]);
+    assert_eq!(
+        changelog.matches("## [0.2.0] - ").count(),
+        1,
+        "{}",
+        changelog
+    );
+    assert_eq!(
+        changelog.matches("## [Unreleased]").count(),
+        1,
+        "{}",
+        changelog
+    );
+    assert_eq!(repo.git(&["status", "--porcelain"]), "");
+}
+
+#[test]
+fn resumed_release_keeps_the_first_backup() {
+    let repo = interrupted_release("resume-backup");
+    succeeded(&repo.rslease(&[&INTERRUPTED[..], &["--resume"]].concat()));
+    let backup = read_to_string(repo.work().join("Cargo.toml.bak")).unwrap();
+    assert_eq!(manifest_version(&backup), "0.2.0-dev");
+    let backup = read_to_string(repo.work().join("CHANGELOG.md.bak")).unwrap();
+    assert!(!backup.contains("## [0.2.0]"), "{}", backup);
+}
+
+#[test]
+fn new_version_file_is_committed() {
+    let repo = released_crate("version-file", ".", "0.1.0", "0.2.0-dev");
+    succeeded(&repo.rslease(&["--local", "--version-file", "VERSION"]));
+    assert_eq!(repo.git(&["show", "v0.2.0:VERSION"]), "0.2.0");
+    assert_eq!(repo.git(&["show", "HEAD:VERSION"]), "0.3.0-dev");
+    assert_eq!(repo.git(&["status", "--porcelain"]), "");
+}
+
+#[test]
+fn fresh_verification_checks_the_crate_in_its_subdirectory() {
+    let repo = released_crate("fresh-subdirectory", "crate", "0.1.0", "0.2.0-dev");
+    succeeded(&repo.rslease(&["--local", "--repo", "crate", "--fresh-verify"]));
+    assert_eq!(repo.tags(), ["v0.1.0", "v0.2.0"]);
+    assert_eq!(repo.git(&["status", "--porcelain"]), "");
+}
+
+/// The top-level files of the docs pushed to gh-pages.
+fn published_docs(repo: &Repo) -> Vec<String> {
+    repo.git(&["fetch", "--quiet", "origin", "gh-pages"]);
+    let files = repo.git(&["ls-tree", "--name-only", "FETCH_HEAD"]);
+    files.lines().map(ToOwned::to_owned).collect()
+}
+
+#[test]
+fn docs_of_a_crate_in_a_subdirectory_are_published() {
+    let repo = released_crate("docs-subdirectory", "crate", "0.1.0", "0.2.0-dev");
+    repo.publish();
+    succeeded(&repo.rslease(&["--repo", "crate", "--publish-docs"]));
+    let docs = published_docs(&repo);
+    for file in ["fixture", "index.html", ".nojekyll"] {
+        assert!(docs.iter().any(|f| f == file), "{} not in {:?}", file, docs);
+    }
+}
+
+#[test]
+fn docs_of_a_virtual_workspace_cover_every_member() {
+    let repo = lockstep_workspace("docs-workspace");
+    repo.publish();
+    succeeded(&repo.rslease(&["--workspace", "--publish-docs"]));
+    let docs = published_docs(&repo);
+    for member in MEMBERS {
+        assert!(
+            docs.iter().any(|f| f == member),
+            "{} not in {:?}",
+            member,
+            docs
+        );
+    }
+    assert!(!docs.iter().any(|f| f == "index.html"), "{:?}", docs);
+}
+
+#[test]
+fn prerelease_gets_no_development_version() {
+    let repo = released_crate("prerelease-dev", ".", "1.2.3", "1.3.0-dev");
+    for args in [
+        &["--increment", "minor+1,patch=0,pre=rc.1"][..],
+        &["--set-version", "1.3.0-rc.1"],
+    ] {
+        let mut args = args.to_vec();
+        args.extend(["--local", "--dry-run"]);
+        let stdout = succeeded(&repo.rslease(&args));
+        assert!(
+            stdout.starts_with("Would release 1.3.0-rc.1 "),
+            "{}",
+            stdout
+        );
+        assert!(!stdout.contains("Would then bump"), "{}", stdout);
+    }
+    let stdout = succeeded(&repo.rslease(&["--set-version", "1.3.0", "--local", "--dry-run"]));
+    assert!(
+        stdout.contains("Would then bump to 1.4.0-dev"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn workspace_member_plan_commits_the_lockfile_of_its_workspace() {
+    let repo = workspace_member("member-plan");
+    let plan = succeeded(&repo.rslease(&[
+        "--local",
+        "--repo",
+        "crate",
+        "--commit-paths",
+        "--commit-lock-separately",
+        "--plan",
+        "--dump-commands",
+    ]));
+    for line in [
+        "git add Cargo.toml ../Cargo.lock\n",
+        "git status --porcelain -- ../Cargo.lock\n",
+        "git add ../Cargo.lock\n",
+    ] {
+        assert!(plan.contains(line), "{} not in:\n{}", line, plan);
+    }
+    let lock = plan.split_whitespace().any(|word| word == "Cargo.lock");
+    assert!(!lock, "{}", plan);
+}
+
+#[test]
+fn plan_has_a_development_version_only_when_the_release_does() {
+    let repo = released_crate("plan-dev", ".", "1.2.3", "1.3.0-dev");
+    let plan = |args: &[&str]| {
+        let mut args = args.to_vec();
+        args.extend(["--local", "--plan", "--dump-commands"]);
+        succeeded(&repo.rslease(&args))
+    };
+    assert!(plan(&[]).contains("git commit -am Post-release."));
+    assert!(!plan(&["--set-version", "1.3.0-rc.1"]).contains("git commit -am Post-release."));
+    assert!(!plan(&["--patch", "--for", "1"]).contains("git commit -am Post-release."));
+}